@@ -0,0 +1,145 @@
+use crate::claude::{self, ClaudeResponse};
+use crate::constants::ULTRATHINK_THRESHOLD;
+use crate::context::{AppContext, ModelTier};
+use crate::git::ChangeSet;
+use crate::ui::Progress;
+use anyhow::Result;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+/// the inputs to a generation, so a speculative generation can be matched
+/// with the one the user asks for
+#[derive(PartialEq)]
+struct Request {
+    prompt: String,
+    model: ModelTier,
+    think_hard: bool,
+    ultrathink: bool,
+}
+
+impl Request {
+    fn new(ctx: &AppContext, changeset: &ChangeSet) -> Self {
+        Self {
+            prompt: claude::get_prompt(ctx, changeset, 0).text(),
+            model: ctx.model,
+            think_hard: ctx.think_hard,
+            ultrathink: ctx.manual_reroll_count > ULTRATHINK_THRESHOLD,
+        }
+    }
+}
+
+/// a generation of every candidate, running on its own thread so the user
+/// can cancel it, or so it can run speculatively while the user reads
+pub struct Job {
+    request: Request,
+    /// projected cost in USD, counted against the daily budget until the
+    /// job's usage is recorded
+    projected_cost: f64,
+    progress: Progress,
+    handle: JoinHandle<Vec<Result<ClaudeResponse>>>,
+}
+
+/// what a job generated
+pub struct Output {
+    /// each candidate's result
+    pub results: Vec<Result<ClaudeResponse>>,
+    /// warnings raised while generating, for the caller to show if it wants
+    pub warnings: Vec<String>,
+}
+
+impl Job {
+    /// start generating with a snapshot of `ctx`; `progress` previews the
+    /// first candidate
+    pub fn start(ctx: &AppContext, changeset: &Arc<ChangeSet>, progress: Progress) -> Self {
+        let request = Request::new(ctx, changeset);
+        let projected_cost = claude::estimate(ctx, changeset).total_cost().unwrap_or(0.0);
+        let ctx = ctx.clone();
+        let changeset = Arc::clone(changeset);
+        let thread_progress = progress.clone();
+        let handle = std::thread::spawn(move || generate(&ctx, &changeset, &thread_progress));
+        Self {
+            request,
+            projected_cost,
+            progress,
+            handle,
+        }
+    }
+
+    /// true if this job generates what `ctx` would
+    pub fn matches(&self, ctx: &AppContext, changeset: &ChangeSet) -> bool {
+        self.request == Request::new(ctx, changeset)
+    }
+
+    pub fn projected_cost(&self) -> f64 {
+        self.projected_cost
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// ask the providers to stop; `join` then returns promptly
+    pub fn cancel(&self) {
+        self.progress.cancel();
+    }
+
+    /// wait for the job, returning each candidate's result
+    pub fn join(self) -> Output {
+        let results = self
+            .handle
+            .join()
+            .unwrap_or_else(|_| vec![Err(anyhow::anyhow!("generation thread panicked"))]);
+        Output {
+            results,
+            warnings: self.progress.take_warnings(),
+        }
+    }
+}
+
+/// generate every candidate in parallel; only the first is previewed
+fn generate(
+    ctx: &AppContext,
+    changeset: &ChangeSet,
+    progress: &Progress,
+) -> Vec<Result<ClaudeResponse>> {
+    let muted = progress.muted();
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..ctx.config.candidates)
+            .map(|candidate| {
+                let progress = if candidate == 0 { progress } else { &muted };
+                scope.spawn(move || claude::generate(ctx, changeset, candidate, progress))
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow::anyhow!("generation thread panicked")))
+            })
+            .collect()
+    })
+}
+
+/// contexts for the generations the user is likely to ask for next, as
+/// configured: the multi-line message, and a reroll
+pub fn speculative(ctx: &AppContext) -> Vec<AppContext> {
+    // mirror the state process_changes sets up for the long and reroll actions
+    let reroll = |multi_line| {
+        let mut next = ctx.clone();
+        next.multi_line = multi_line;
+        next.model = ModelTier::Smart;
+        next.think_hard = true;
+        next.manual_reroll_count += 1;
+        next
+    };
+
+    let mut contexts = Vec::new();
+    if ctx.config.speculate_long && !ctx.multi_line {
+        contexts.push(reroll(true));
+    }
+    if ctx.config.speculate_reroll {
+        contexts.push(reroll(ctx.multi_line));
+    }
+    contexts
+}
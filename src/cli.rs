@@ -1,4 +1,5 @@
-use clap::Parser;
+use crate::git::Scope;
+use clap::{Parser, Subcommand, ValueEnum};
 
 /// git-auto-commit: analyse git changes and display files touched with their change types
 #[derive(Parser, Debug)]
@@ -11,14 +12,42 @@ use clap::Parser;
 #[allow(clippy::struct_excessive_bools)]
 #[allow(clippy::struct_field_names)]
 pub struct Cli {
-    /// force CLI usage
-    #[arg(long, conflicts_with = "api")]
+    /// force CLI usage (same as --provider claude-cli)
+    #[arg(long, conflicts_with_all = ["api", "provider"])]
     pub cli: bool,
 
-    /// force API usage
-    #[arg(long, conflicts_with = "cli")]
+    /// force API usage (same as --provider claude-api)
+    #[arg(long, conflicts_with_all = ["cli", "provider"])]
     pub api: bool,
 
+    /// provider used to generate the commit message, overriding config
+    #[arg(long, value_name = "NAME")]
+    pub provider: Option<String>,
+
+    /// number of commit messages to generate and choose from, overriding config
+    #[arg(long, value_name = "N")]
+    pub candidates: Option<usize>,
+
+    /// choose which files and hunks to commit before generating
+    #[arg(short, long)]
+    pub patch: bool,
+
+    /// commit staged and unstaged changes to tracked files, like `git commit -a`
+    #[arg(short, long, conflicts_with_all = ["amend", "split"])]
+    pub all: bool,
+
+    /// commit staged and unstaged changes, including untracked files
+    #[arg(short, long, conflicts_with_all = ["all", "amend", "split"])]
+    pub untracked: bool,
+
+    /// regenerate the message of HEAD, including any staged changes, and amend it
+    #[arg(long, conflicts_with_all = ["patch", "split"])]
+    pub amend: bool,
+
+    /// split unstaged changes into several commits, as proposed by claude
+    #[arg(long, conflicts_with = "patch")]
+    pub split: bool,
+
     /// print the prompt sent to claude
     #[arg(long)]
     pub debug_prompt: bool,
@@ -26,10 +55,76 @@ pub struct Cli {
     /// print the full JSON response from claude
     #[arg(long)]
     pub debug_response: bool,
+
+    /// only commit changes to paths matching these pathspecs
+    #[arg(last = true, value_name = "PATHSPEC", conflicts_with = "amend")]
+    pub pathspecs: Vec<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// summarise spend and acceptance rates from the usage ledger
+    Stats {
+        /// group by a single field; the table shows all groupings by default
+        #[arg(long, value_enum)]
+        by: Option<StatsGroup>,
+
+        /// output format; csv and json export every entry unless --by is given
+        #[arg(long, value_enum, default_value_t = StatsFormat::Table)]
+        format: StatsFormat,
+    },
+
+    /// generate new messages for a range of commits and rewrite the branch
+    Reword {
+        /// commits to reword, eg. main..HEAD, or HEAD~3 for the last three;
+        /// must end at HEAD
+        range: String,
+    },
+}
+
+/// how to group ledger entries
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum StatsGroup {
+    Repo,
+    Model,
+    Week,
+}
+
+/// output format for stats
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum StatsFormat {
+    Table,
+    Csv,
+    Json,
 }
 
 impl Cli {
     pub fn parse_args() -> Self {
         Self::parse()
     }
+
+    /// which changes to commit
+    pub fn scope(&self) -> Scope {
+        if self.untracked {
+            Scope::All
+        } else if self.all {
+            Scope::Tracked
+        } else {
+            Scope::Auto
+        }
+    }
+
+    /// provider name requested on the command line, if any
+    pub fn provider_name(&self) -> Option<&str> {
+        if self.cli {
+            Some("claude-cli")
+        } else if self.api {
+            Some("claude-api")
+        } else {
+            self.provider.as_deref()
+        }
+    }
 }
@@ -0,0 +1,492 @@
+use crate::constants::{
+    CANDIDATES, CLAUDE_API_BASE_URL, CLAUDE_API_MODELS, CLAUDE_TIMEOUT_SECS, DEFAULT_CONTEXT,
+    DIFF_SIZE_MAXIMUM_BYTES, DIFF_SIZE_WARNING_BYTES, MAX_CANDIDATES, MAX_LINE_LENGTH, MODEL_FAST,
+    MODEL_SMART, OLLAMA_BASE_URL, OPENAI_BASE_URL, PROVIDER, REFUSE_OVER_BUDGET,
+    RETRY_DEADLINE_SECS, SPECULATE_LONG, SPECULATE_REROLL, STREAM,
+};
+use crate::context::ModelTier;
+use crate::pricing::Price;
+use anyhow::{Result, bail};
+use git2::Repository;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+const CONFIG_DIR_NAME: &str = "git-auto-commit";
+const CONFIG_FILE_NAME: &str = "config.toml";
+const LEGACY_CONFIG_FILE_NAME: &str = "config";
+const REPO_CONFIG_FILE_NAME: &str = ".git-auto-commit.toml";
+const GIT_CONFIG_SECTION: &str = "autocommit";
+
+// keys the repository file can't set, as anyone who can commit to a
+// repository could use them against whoever runs the tool in a clone
+const REPO_FORBIDDEN_KEYS: &[&str] = &[
+    // running arbitrary commands
+    "provider",
+    "command",
+    "replay",
+    // sending the diff, and any api key, to another host
+    "claude-api.base-url",
+    "claude-api.headers",
+    "openai.base-url",
+    "ollama.base-url",
+    // writing to any file the user can write
+    "ledger-file",
+    // raising or disabling the user's spending limits
+    "budget-per-call",
+    "budget-per-day",
+    "refuse-over-budget",
+    "pricing",
+];
+
+/// resolved configuration, merged from (lowest to highest precedence):
+/// - built-in defaults
+/// - the user's `~/.config/git-auto-commit/config.toml`
+/// - the repository's `.git-auto-commit.toml`
+/// - `autocommit.*` git config keys
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Config {
+    /// name of the provider used to generate commit messages (see provider.rs)
+    pub provider: String,
+
+    /// anthropic api key; when set the api is used instead of the cli
+    pub api_key: Option<String>,
+
+    /// maximum length of each line of the commit message
+    pub max_line_length: usize,
+
+    /// number of context lines for git unified diff
+    pub context_lines: u32,
+
+    /// diff size at which context is reduced and a warning is shown
+    pub diff_size_warning_bytes: usize,
+
+    /// diff size at which generation is refused
+    pub diff_size_maximum_bytes: usize,
+
+    /// files whose diffs are left out of the prompt as generated, as git
+    /// pathspecs relative to the repository root, in addition to those marked
+    /// `linguist-generated` in .gitattributes
+    pub generated: Vec<String>,
+
+    /// model used for the initial generation
+    pub model_fast: String,
+
+    /// model used when rerolling
+    pub model_smart: String,
+
+    /// how long to wait for claude before giving up
+    pub timeout_secs: u64,
+
+    /// how long to keep retrying rate-limited or overloaded requests; 0 disables retries
+    pub retry_deadline_secs: u64,
+
+    /// stream responses, showing the message as it is generated
+    pub stream: bool,
+
+    /// number of messages generated in parallel for the user to choose from
+    pub candidates: usize,
+
+    /// generate the multi-line message in the background while the user reads
+    /// the single-line one
+    pub speculate_long: bool,
+
+    /// generate the next reroll in the background while the user reads the
+    /// current message
+    pub speculate_reroll: bool,
+
+    /// prices keyed by api model id, in addition to the built-in prices
+    pub pricing: BTreeMap<String, Price>,
+
+    /// maximum projected cost in USD of a single generation
+    pub budget_per_call: Option<f64>,
+
+    /// maximum cost in USD of all generations in a (UTC) day
+    pub budget_per_day: Option<f64>,
+
+    /// refuse generations which would exceed a budget, rather than asking
+    pub refuse_over_budget: bool,
+
+    /// where generations are recorded; defaults to the user's data directory
+    pub ledger_file: Option<PathBuf>,
+
+    /// settings for the claude-api provider
+    pub claude_api: ClaudeApiConfig,
+
+    /// settings for the openai provider
+    pub openai: OpenAiConfig,
+
+    /// settings for the ollama provider
+    pub ollama: OllamaConfig,
+
+    /// settings for the command provider
+    pub command: CommandConfig,
+
+    /// settings for the replay provider
+    pub replay: ReplayConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            provider: PROVIDER.to_string(),
+            api_key: None,
+            max_line_length: MAX_LINE_LENGTH,
+            context_lines: DEFAULT_CONTEXT,
+            diff_size_warning_bytes: DIFF_SIZE_WARNING_BYTES,
+            diff_size_maximum_bytes: DIFF_SIZE_MAXIMUM_BYTES,
+            generated: Vec::new(),
+            model_fast: MODEL_FAST.to_string(),
+            model_smart: MODEL_SMART.to_string(),
+            timeout_secs: CLAUDE_TIMEOUT_SECS,
+            retry_deadline_secs: RETRY_DEADLINE_SECS,
+            stream: STREAM,
+            candidates: CANDIDATES,
+            speculate_long: SPECULATE_LONG,
+            speculate_reroll: SPECULATE_REROLL,
+            pricing: BTreeMap::new(),
+            budget_per_call: None,
+            budget_per_day: None,
+            refuse_over_budget: REFUSE_OVER_BUDGET,
+            ledger_file: None,
+            claude_api: ClaudeApiConfig::default(),
+            openai: OpenAiConfig::default(),
+            ollama: OllamaConfig::default(),
+            command: CommandConfig::default(),
+            replay: ReplayConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// claude model name for the given tier
+    pub fn model(&self, tier: ModelTier) -> &str {
+        match tier {
+            ModelTier::Fast => &self.model_fast,
+            ModelTier::Smart => &self.model_smart,
+        }
+    }
+}
+
+/// settings for the claude-api provider
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ClaudeApiConfig {
+    /// api base url, including the version; change to use a proxy
+    pub base_url: String,
+
+    /// extra headers sent with every request
+    pub headers: BTreeMap<String, String>,
+
+    /// model name to api model id mapping, in addition to the built-in mapping
+    pub models: BTreeMap<String, String>,
+
+    /// use extended thinking when rerolling, instead of asking in the prompt
+    pub thinking: bool,
+}
+
+impl Default for ClaudeApiConfig {
+    fn default() -> Self {
+        Self {
+            base_url: CLAUDE_API_BASE_URL.to_string(),
+            headers: BTreeMap::new(),
+            models: BTreeMap::new(),
+            thinking: true,
+        }
+    }
+}
+
+impl ClaudeApiConfig {
+    /// api model id for a model name; names without a mapping are used as-is
+    ///
+    /// names are matched case-insensitively as git config lowercases keys
+    pub fn model_id<'a>(&'a self, name: &'a str) -> &'a str {
+        self.models
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .chain(CLAUDE_API_MODELS.iter().copied())
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map_or(name, |(_, v)| v)
+    }
+}
+
+/// settings for the openai provider, which works with any server implementing
+/// the `/v1/chat/completions` api
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct OpenAiConfig {
+    /// api base url, including the version (eg. `http://localhost:8080/v1`)
+    pub base_url: String,
+
+    /// sent as a bearer token when set
+    pub api_key: Option<String>,
+
+    /// model used for the initial generation
+    pub model_fast: Option<String>,
+
+    /// model used when rerolling; defaults to model-fast
+    pub model_smart: Option<String>,
+}
+
+impl Default for OpenAiConfig {
+    fn default() -> Self {
+        Self {
+            base_url: OPENAI_BASE_URL.to_string(),
+            api_key: None,
+            model_fast: None,
+            model_smart: None,
+        }
+    }
+}
+
+/// settings for the ollama provider, which runs models locally
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct OllamaConfig {
+    /// url of the ollama server
+    pub base_url: String,
+
+    /// model used for the initial generation
+    pub model_fast: Option<String>,
+
+    /// model used when rerolling; defaults to model-fast
+    pub model_smart: Option<String>,
+}
+
+impl Default for OllamaConfig {
+    fn default() -> Self {
+        Self {
+            base_url: OLLAMA_BASE_URL.to_string(),
+            model_fast: None,
+            model_smart: None,
+        }
+    }
+}
+
+/// settings for the command provider, which pipes the prompt into an
+/// arbitrary program and reads the commit message from its stdout
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CommandConfig {
+    /// shell command to run
+    pub command: Option<String>,
+
+    /// parse stdout as a json envelope containing the message and token usage
+    pub json: bool,
+}
+
+/// settings for the replay provider, which replays recorded responses
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ReplayConfig {
+    /// directory containing the recorded responses
+    pub dir: Option<PathBuf>,
+
+    /// provider used to record responses which haven't been recorded yet
+    pub record: Option<String>,
+}
+
+/// load configuration for the repository containing `path`
+pub fn load(path: &Path) -> Result<Config> {
+    load_from(&user_config_dirs(), path)
+}
+
+/// load configuration using the given user config directories (first match wins)
+fn load_from(user_dirs: &[PathBuf], path: &Path) -> Result<Config> {
+    let mut merged = Table::new();
+
+    // user config, with the legacy `api-key=` file underneath config.toml
+    if let Some(legacy) = first_existing(user_dirs, LEGACY_CONFIG_FILE_NAME) {
+        merge(&mut merged, read_legacy(&legacy)?);
+    }
+    if let Some(user) = first_existing(user_dirs, CONFIG_FILE_NAME) {
+        merge(&mut merged, read_toml(&user)?);
+    }
+
+    // repository config
+    let repo = Repository::discover(path).ok();
+    if let Some(workdir) = repo.as_ref().and_then(Repository::workdir) {
+        let repo_config = workdir.join(REPO_CONFIG_FILE_NAME);
+        if repo_config.exists() {
+            let table = read_toml(&repo_config)?;
+            check_repo_keys(&table, &repo_config)?;
+            merge(&mut merged, table);
+        }
+    }
+
+    // git config
+    if let Some(repo) = &repo {
+        merge(&mut merged, read_git_config(repo)?);
+    }
+
+    let config = Config::deserialize(Value::Table(merged))
+        .map_err(|e| anyhow::anyhow!("invalid configuration: {}", e.message()))?;
+    validate(&config)?;
+    Ok(config)
+}
+
+/// candidate user config directories, in order of preference
+fn user_config_dirs() -> Vec<PathBuf> {
+    // try dirs::config_dir() first (platform-specific)
+    let mut paths = Vec::new();
+    if let Some(config_dir) = dirs::config_dir() {
+        paths.push(config_dir.join(CONFIG_DIR_NAME));
+    }
+
+    // also try ~/.config explicitly if it's different from config_dir()
+    if let Some(home_dir) = dirs::home_dir() {
+        let dotconfig_path = home_dir.join(".config").join(CONFIG_DIR_NAME);
+        if !paths.contains(&dotconfig_path) {
+            paths.push(dotconfig_path);
+        }
+    }
+
+    paths
+}
+
+fn first_existing(dirs: &[PathBuf], file_name: &str) -> Option<PathBuf> {
+    dirs.iter()
+        .map(|dir| dir.join(file_name))
+        .find(|path| path.exists())
+}
+
+fn read_toml(path: &Path) -> Result<Table> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("failed to read {}: {e}", path.display()))?;
+    contents
+        .parse::<Table>()
+        .map_err(|e| anyhow::anyhow!("failed to parse {}: {}", path.display(), e.message()))
+}
+
+/// read the legacy config file, which only supports `api-key=...`
+fn read_legacy(path: &Path) -> Result<Table> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("failed to read {}: {e}", path.display()))?;
+
+    let mut table = Table::new();
+    if let Some(api_key) = contents
+        .lines()
+        .find_map(|line| line.strip_prefix("api-key="))
+        .filter(|api_key| !api_key.is_empty())
+    {
+        table.insert("api-key".to_string(), Value::String(api_key.to_string()));
+    }
+    Ok(table)
+}
+
+/// read `autocommit.*` keys from git config
+///
+/// `autocommit.<key>` maps to a top-level key and `autocommit.<table>.<key>`
+/// maps to a key within a table (nested tables are separated by dots).
+/// values are strings for keys which take one, and are otherwise typed as
+/// toml (so numbers, booleans and lists work)
+fn read_git_config(repo: &Repository) -> Result<Table> {
+    let config = repo
+        .config()
+        .map_err(|e| anyhow::anyhow!("failed to read git config: {}", e.message()))?;
+    let mut entries = config
+        .entries(Some(&format!("^{GIT_CONFIG_SECTION}\\.")))
+        .map_err(|e| anyhow::anyhow!("failed to read git config: {}", e.message()))?;
+
+    let mut table = Table::new();
+    while let Some(entry) = entries.next() {
+        let entry =
+            entry.map_err(|e| anyhow::anyhow!("failed to read git config: {}", e.message()))?;
+        let (Some(name), Some(value)) = (entry.name(), entry.value()) else {
+            continue;
+        };
+        let Some(name) = name.strip_prefix(&format!("{GIT_CONFIG_SECTION}.")) else {
+            continue;
+        };
+
+        merge(&mut table, nested(name, git_config_value(name, value)));
+    }
+
+    Ok(table)
+}
+
+/// a table setting the dotted key `name` to `value`
+fn nested(name: &str, value: Value) -> Table {
+    // build the nested table from the innermost key outwards
+    let mut value = value;
+    for key in name.rsplit('.') {
+        let mut layer = Table::new();
+        layer.insert(key.to_string(), value);
+        value = Value::Table(layer);
+    }
+    match value {
+        Value::Table(table) => table,
+        _ => Table::new(),
+    }
+}
+
+/// interpret a git config value for the dotted key `name`: as a string if the
+/// key takes one, so eg. an api key made of digits stays a string, and
+/// otherwise as a toml value, falling back to a string
+fn git_config_value(name: &str, value: &str) -> Value {
+    let string = Value::String(value.to_string());
+    if Config::deserialize(Value::Table(nested(name, string.clone()))).is_ok() {
+        return string;
+    }
+    format!("value = {value}")
+        .parse::<Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or(string)
+}
+
+/// fail if the repository file sets a key it can't be trusted with
+fn check_repo_keys(table: &Table, path: &Path) -> Result<()> {
+    fn contains(table: &Table, key: &str) -> bool {
+        match key.split_once('.') {
+            Some((key, rest)) => {
+                matches!(table.get(key), Some(Value::Table(inner)) if contains(inner, rest))
+            }
+            None => table.contains_key(key),
+        }
+    }
+
+    if let Some(key) = REPO_FORBIDDEN_KEYS.iter().find(|key| contains(table, key)) {
+        bail!(
+            "{} can't set {key}; set it in your user config or git config instead",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// recursively merge `overlay` into `base`; tables are merged, everything else replaced
+fn merge(base: &mut Table, overlay: Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base_table)), Value::Table(overlay_table)) => {
+                merge(base_table, overlay_table);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// fail if a value is out of range
+fn validate(config: &Config) -> Result<()> {
+    if config.max_line_length == 0 {
+        bail!("invalid configuration: max-line-length must be greater than zero");
+    }
+    if !(1..=MAX_CANDIDATES).contains(&config.candidates) {
+        bail!("invalid configuration: candidates must be between 1 and {MAX_CANDIDATES}");
+    }
+    if config.diff_size_warning_bytes > config.diff_size_maximum_bytes {
+        bail!(
+            "invalid configuration: diff-size-warning-bytes must not exceed diff-size-maximum-bytes"
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests;
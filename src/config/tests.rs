@@ -0,0 +1,277 @@
+use super::*;
+use std::fs;
+use tempfile::TempDir;
+
+/// helper to create a user config directory and a repository
+fn setup() -> (TempDir, TempDir, Repository) {
+    let user_dir = TempDir::new().unwrap();
+    let repo_dir = TempDir::new().unwrap();
+    let repo = Repository::init(repo_dir.path()).unwrap();
+    (user_dir, repo_dir, repo)
+}
+
+#[test]
+fn test_defaults() {
+    let (user_dir, repo_dir, _repo) = setup();
+
+    let config = load_from(&[user_dir.path().to_path_buf()], repo_dir.path()).unwrap();
+
+    assert_eq!(config.api_key, None);
+    assert_eq!(config.max_line_length, MAX_LINE_LENGTH);
+    assert_eq!(config.context_lines, DEFAULT_CONTEXT);
+    assert_eq!(config.model_fast, MODEL_FAST);
+    assert_eq!(config.model_smart, MODEL_SMART);
+    assert_eq!(config.timeout_secs, CLAUDE_TIMEOUT_SECS);
+}
+
+#[test]
+fn test_legacy_api_key() {
+    let (user_dir, repo_dir, _repo) = setup();
+    fs::write(
+        user_dir.path().join(LEGACY_CONFIG_FILE_NAME),
+        "api-key=legacy-key\n",
+    )
+    .unwrap();
+
+    let config = load_from(&[user_dir.path().to_path_buf()], repo_dir.path()).unwrap();
+    assert_eq!(config.api_key.as_deref(), Some("legacy-key"));
+
+    // config.toml takes precedence over the legacy file
+    fs::write(
+        user_dir.path().join(CONFIG_FILE_NAME),
+        "api-key = \"toml-key\"\n",
+    )
+    .unwrap();
+
+    let config = load_from(&[user_dir.path().to_path_buf()], repo_dir.path()).unwrap();
+    assert_eq!(config.api_key.as_deref(), Some("toml-key"));
+}
+
+#[test]
+fn test_precedence() {
+    let (user_dir, repo_dir, repo) = setup();
+
+    fs::write(
+        user_dir.path().join(CONFIG_FILE_NAME),
+        "max-line-length = 80\nmodel-fast = \"user-fast\"\nmodel-smart = \"user-smart\"\n",
+    )
+    .unwrap();
+    fs::write(
+        repo_dir.path().join(REPO_CONFIG_FILE_NAME),
+        "max-line-length = 100\nmodel-fast = \"repo-fast\"\n",
+    )
+    .unwrap();
+    repo.config()
+        .unwrap()
+        .set_str("autocommit.max-line-length", "50")
+        .unwrap();
+
+    let config = load_from(&[user_dir.path().to_path_buf()], repo_dir.path()).unwrap();
+
+    // git config beats the repository file, which beats the user file
+    assert_eq!(config.max_line_length, 50);
+    assert_eq!(config.model_fast, "repo-fast");
+    assert_eq!(config.model_smart, "user-smart");
+}
+
+#[test]
+fn test_git_config_values() {
+    let (user_dir, repo_dir, repo) = setup();
+
+    let mut git_config = repo.config().unwrap();
+    git_config.set_str("autocommit.timeout-secs", "90").unwrap();
+    git_config
+        .set_str("autocommit.model-smart", "claude-sonnet-4-5")
+        .unwrap();
+    git_config
+        .set_str("autocommit.generated", r#"["proto/", "*.snap"]"#)
+        .unwrap();
+
+    git_config.set_str("autocommit.stream", "false").unwrap();
+
+    // values which look like toml stay strings for keys which take strings
+    git_config.set_str("autocommit.api-key", "12345").unwrap();
+    git_config
+        .set_str("autocommit.openai.api-key", "true")
+        .unwrap();
+    git_config
+        .set_str("autocommit.command.command", "42")
+        .unwrap();
+    git_config
+        .set_str("autocommit.claude-api.models.Haiku", "3.5")
+        .unwrap();
+
+    let config = load_from(&[user_dir.path().to_path_buf()], repo_dir.path()).unwrap();
+
+    assert_eq!(config.timeout_secs, 90);
+    assert_eq!(config.model_smart, "claude-sonnet-4-5");
+    assert_eq!(config.generated, ["proto/", "*.snap"]);
+    assert!(!config.stream);
+    assert_eq!(config.api_key.as_deref(), Some("12345"));
+    assert_eq!(config.openai.api_key.as_deref(), Some("true"));
+    assert_eq!(config.command.command.as_deref(), Some("42"));
+    assert_eq!(config.claude_api.model_id("Haiku"), "3.5");
+}
+
+#[test]
+fn test_repo_file_cannot_run_commands() {
+    let (user_dir, repo_dir, repo) = setup();
+
+    for contents in [
+        "provider = \"command\"\n",
+        "[command]\ncommand = \"touch pwned\"\n",
+        "[replay]\nrecord = \"command\"\n",
+    ] {
+        fs::write(repo_dir.path().join(REPO_CONFIG_FILE_NAME), contents).unwrap();
+        let error = load_from(&[user_dir.path().to_path_buf()], repo_dir.path()).unwrap_err();
+        assert!(
+            error.to_string().contains("can't set"),
+            "{contents}: {error}"
+        );
+    }
+
+    // the user can still choose the command provider themselves
+    fs::remove_file(repo_dir.path().join(REPO_CONFIG_FILE_NAME)).unwrap();
+    fs::write(
+        user_dir.path().join(CONFIG_FILE_NAME),
+        "[command]\ncommand = \"my-llm\"\n",
+    )
+    .unwrap();
+    repo.config()
+        .unwrap()
+        .set_str("autocommit.provider", "command")
+        .unwrap();
+    let config = load_from(&[user_dir.path().to_path_buf()], repo_dir.path()).unwrap();
+    assert_eq!(config.provider, "command");
+    assert_eq!(config.command.command.as_deref(), Some("my-llm"));
+}
+
+#[test]
+fn test_repo_file_cannot_redirect_output() {
+    let (user_dir, repo_dir, _repo) = setup();
+    fs::write(
+        user_dir.path().join(CONFIG_FILE_NAME),
+        "api-key = \"secret\"\n",
+    )
+    .unwrap();
+
+    for contents in [
+        "[claude-api]\nbase-url = \"https://attacker.example.com\"\n",
+        "[claude-api.headers]\nx-forward = \"1\"\n",
+        "[openai]\nbase-url = \"https://attacker.example.com\"\n",
+        "[ollama]\nbase-url = \"https://attacker.example.com\"\n",
+        "ledger-file = \"/home/user/.bashrc\"\n",
+    ] {
+        fs::write(repo_dir.path().join(REPO_CONFIG_FILE_NAME), contents).unwrap();
+        let error = load_from(&[user_dir.path().to_path_buf()], repo_dir.path()).unwrap_err();
+        assert!(
+            error.to_string().contains("can't set"),
+            "{contents}: {error}"
+        );
+    }
+
+    // other settings in the same tables are allowed
+    fs::write(
+        repo_dir.path().join(REPO_CONFIG_FILE_NAME),
+        "[claude-api]\nthinking = false\n[ollama]\nmodel-fast = \"qwen\"\n",
+    )
+    .unwrap();
+    let config = load_from(&[user_dir.path().to_path_buf()], repo_dir.path()).unwrap();
+    assert!(!config.claude_api.thinking);
+    assert_eq!(config.ollama.model_fast.as_deref(), Some("qwen"));
+}
+
+#[test]
+fn test_repo_file_cannot_change_budgets() {
+    let (user_dir, repo_dir, repo) = setup();
+    fs::write(
+        user_dir.path().join(CONFIG_FILE_NAME),
+        "budget-per-call = 0.1\nrefuse-over-budget = true\n",
+    )
+    .unwrap();
+
+    for contents in [
+        "budget-per-call = 100.0\n",
+        "budget-per-day = 100.0\n",
+        "refuse-over-budget = false\n",
+        "[pricing.claude-sonnet-4-5]\ninput = 0.0\noutput = 0.0\n",
+    ] {
+        fs::write(repo_dir.path().join(REPO_CONFIG_FILE_NAME), contents).unwrap();
+        let error = load_from(&[user_dir.path().to_path_buf()], repo_dir.path()).unwrap_err();
+        assert!(
+            error.to_string().contains("can't set"),
+            "{contents}: {error}"
+        );
+    }
+
+    // git config can still set them
+    fs::remove_file(repo_dir.path().join(REPO_CONFIG_FILE_NAME)).unwrap();
+    let mut git_config = repo.config().unwrap();
+    git_config
+        .set_str("autocommit.budget-per-call", "0.5")
+        .unwrap();
+    let config = load_from(&[user_dir.path().to_path_buf()], repo_dir.path()).unwrap();
+    assert_eq!(config.budget_per_call, Some(0.5));
+    assert!(config.refuse_over_budget);
+}
+
+#[test]
+fn test_invalid_config() {
+    let (user_dir, repo_dir, _repo) = setup();
+
+    // unknown keys are rejected to catch typos
+    fs::write(
+        repo_dir.path().join(REPO_CONFIG_FILE_NAME),
+        "max-line-lenght = 80\n",
+    )
+    .unwrap();
+    let result = load_from(&[user_dir.path().to_path_buf()], repo_dir.path());
+    assert!(result.is_err(), "unknown key should be rejected");
+
+    // values are validated
+    fs::write(
+        repo_dir.path().join(REPO_CONFIG_FILE_NAME),
+        "diff-size-warning-bytes = 2048\ndiff-size-maximum-bytes = 1024\n",
+    )
+    .unwrap();
+    let result = load_from(&[user_dir.path().to_path_buf()], repo_dir.path());
+    assert!(
+        result.is_err(),
+        "warning size above maximum should be rejected"
+    );
+
+    fs::write(
+        repo_dir.path().join(REPO_CONFIG_FILE_NAME),
+        "candidates = 10\n",
+    )
+    .unwrap();
+    let result = load_from(&[user_dir.path().to_path_buf()], repo_dir.path());
+    assert!(
+        result.is_err(),
+        "more candidates than digit keys should be rejected"
+    );
+}
+
+#[test]
+fn test_claude_api_model_ids() {
+    let (user_dir, repo_dir, repo) = setup();
+
+    fs::write(
+        repo_dir.path().join(REPO_CONFIG_FILE_NAME),
+        "[claude-api.models]\nSonnet = \"claude-sonnet-4-5\"\n",
+    )
+    .unwrap();
+    repo.config()
+        .unwrap()
+        .set_str("autocommit.claude-api.models.Opus", "claude-opus-4-1")
+        .unwrap();
+
+    let config = load_from(&[user_dir.path().to_path_buf()], repo_dir.path()).unwrap();
+    let claude_api = &config.claude_api;
+
+    // built-in, overridden, git config (lowercased by git), and unmapped names
+    assert_eq!(claude_api.model_id("Haiku"), "claude-3-5-haiku-20241022");
+    assert_eq!(claude_api.model_id("Sonnet"), "claude-sonnet-4-5");
+    assert_eq!(claude_api.model_id("Opus"), "claude-opus-4-1");
+    assert_eq!(claude_api.model_id("claude-x"), "claude-x");
+}
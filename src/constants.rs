@@ -1,20 +1,63 @@
+// defaults for values which can be overridden in config (see config.rs)
+
 // commit message
 pub const MAX_LINE_LENGTH: usize = 72;
-pub const MIN_SAFE_LINE_LENGTH: usize = 60;
-pub const MAX_SAFE_LINE_LENGTH: usize = 70;
-
-// ui
-pub const MAX_FILES_TO_SHOW: usize = 10;
-pub const MAX_AUTO_REROLLS: usize = 3;
 
 // diff
 pub const DEFAULT_CONTEXT: u32 = 3;
-pub const LESS_CONTEXT: u32 = 1;
 pub const DIFF_SIZE_WARNING_BYTES: usize = 50 * 1024;
 pub const DIFF_SIZE_MAXIMUM_BYTES: usize = 100 * 1024;
 
 // claude
+pub const PROVIDER: &str = "auto";
 pub const CLAUDE_TIMEOUT_SECS: u64 = 30;
+pub const STREAM: bool = true;
+pub const REFUSE_OVER_BUDGET: bool = false;
+pub const RETRY_DEADLINE_SECS: u64 = 60;
+pub const CANDIDATES: usize = 1;
+pub const SPECULATE_LONG: bool = false;
+pub const SPECULATE_REROLL: bool = false;
 pub const MODEL_FAST: &str = "Haiku";
 pub const MODEL_SMART: &str = "Sonnet";
+pub const CLAUDE_API_BASE_URL: &str = "https://api.anthropic.com/v1";
+// model names which map to api model ids; other names are used as-is
+pub const CLAUDE_API_MODELS: &[(&str, &str)] = &[
+    ("Haiku", "claude-3-5-haiku-20241022"),
+    ("Sonnet", "claude-3-7-sonnet-20250219"),
+];
+
+// openai
+pub const OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+
+// ollama
+pub const OLLAMA_BASE_URL: &str = "http://localhost:11434";
+
+// fixed values
+
+// commit message
+// the prompt asks for lines within this many chars below the maximum
+pub const SAFE_LINE_LENGTH_MIN_MARGIN: usize = 12;
+pub const SAFE_LINE_LENGTH_MAX_MARGIN: usize = 2;
+
+// ui
+pub const MAX_FILES_TO_SHOW: usize = 10;
+pub const MAX_AUTO_REROLLS: usize = 3;
+// candidates are chosen with a single digit key
+pub const MAX_CANDIDATES: usize = 9;
+
+// diff
+pub const LESS_CONTEXT: u32 = 1;
+
+// claude
 pub const ULTRATHINK_THRESHOLD: usize = 2;
+// rough size of a token, and of a response, for estimates
+pub const CHARS_PER_TOKEN: usize = 4;
+pub const ESTIMATED_OUTPUT_TOKENS: u64 = 200;
+// api thinking budgets, matching the cli's "think hard" and "ultrathink"
+pub const THINK_HARD_BUDGET_TOKENS: u32 = 10_000;
+pub const ULTRATHINK_BUDGET_TOKENS: u32 = 31_999;
+// delay before the first retry, doubling for each subsequent retry
+pub const RETRY_INITIAL_DELAY_MS: u64 = 1000;
+pub const RETRY_MAX_DELAY_SECS: u64 = 20;
+// how often to check whether the user has cancelled generation
+pub const CANCEL_POLL_MS: u64 = 50;
@@ -1,17 +1,27 @@
+use crate::config::Config;
+use crate::provider::Provider;
+use std::sync::Arc;
+
 /// application context holding state throughout the commit generation workflow
+///
+/// cloned to generate in the background while the user interacts
+#[derive(Clone)]
 #[allow(clippy::struct_excessive_bools)]
 pub struct AppContext {
     /// the current commit description
     pub commit_description: String,
 
+    /// generated descriptions to choose from, when more than one is generated
+    pub candidates: Vec<String>,
+
     /// whether to generate multi-line commit messages
     pub multi_line: bool,
 
     /// number of context lines for git unified diff
     pub context_lines: u32,
 
-    /// which model claude should use
-    pub model: String,
+    /// which model tier to use for the next generation
+    pub model: ModelTier,
 
     /// whether to enable "think hard" mode for generation
     pub think_hard: bool,
@@ -31,8 +41,17 @@ pub struct AppContext {
     /// whether the user has manually edited the commit description
     pub user_edited: bool,
 
-    /// how to interact with claude
-    pub claude_method: ClaudeMethod,
+    /// ledger id of the generation being shown, until its outcome is recorded
+    pub generation_id: Option<String>,
+
+    /// cost in USD of discarded speculative generations, until it is shown
+    pub unused_cost: f64,
+
+    /// backend used to generate commit messages
+    pub provider: Arc<dyn Provider>,
+
+    /// configuration loaded from config files and git config
+    pub config: Config,
 
     /// whether to show the claude prompt (from --debug-prompt flag)
     pub debug_prompt: bool,
@@ -43,14 +62,20 @@ pub struct AppContext {
 
 impl AppContext {
     /// create a new context with default values
-    pub fn new(claude_method: ClaudeMethod, debug_prompt: bool, debug_response: bool) -> Self {
+    pub fn new(
+        config: Config,
+        provider: Box<dyn Provider>,
+        debug_prompt: bool,
+        debug_response: bool,
+    ) -> Self {
         Self {
             // commit desc
             commit_description: String::from("bug fixes and/or improvements"),
+            candidates: Vec::new(),
             multi_line: false,
             // prompt
-            context_lines: crate::constants::DEFAULT_CONTEXT,
-            model: crate::constants::MODEL_FAST.to_string(),
+            context_lines: config.context_lines,
+            model: ModelTier::Fast,
             think_hard: false,
             prompt_extra: String::new(),
             // state
@@ -58,17 +83,40 @@ impl AppContext {
             auto_reroll_count: 0,
             manual_reroll_count: 0,
             user_edited: false,
+            generation_id: None,
+            unused_cost: 0.0,
             // claude
-            claude_method,
+            provider: Arc::from(provider),
+            config,
             // debugging
             debug_prompt,
             debug_response,
         }
     }
+
+    /// reset the state for generating another commit's message, eg. when
+    /// rewording several commits, keeping the settings
+    pub fn reset(&mut self) {
+        self.commit_description = String::from("bug fixes and/or improvements");
+        self.candidates.clear();
+        self.multi_line = false;
+        self.context_lines = self.config.context_lines;
+        self.model = ModelTier::Fast;
+        self.think_hard = false;
+        self.prompt_extra.clear();
+        self.regenerate = true;
+        self.auto_reroll_count = 0;
+        self.manual_reroll_count = 0;
+        self.user_edited = false;
+        self.generation_id = None;
+    }
 }
 
-pub enum ClaudeMethod {
-    Auto,
-    Cli,
-    Api,
+/// model tier; each provider maps tiers to its own model names
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModelTier {
+    /// used for the initial generation
+    Fast,
+    /// used when rerolling
+    Smart,
 }
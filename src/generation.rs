@@ -0,0 +1,724 @@
+use crate::background::{self, Job};
+use crate::claude;
+use crate::config::Config;
+use crate::constants::{LESS_CONTEXT, MAX_AUTO_REROLLS, MAX_FILES_TO_SHOW};
+use crate::context::{AppContext, ModelTier};
+use crate::git::{ChangeSet, FileChange, FileType, Scope, status_char};
+use crate::ledger;
+use crate::ui::{Input, Progress};
+use crate::{error, info, status, warning};
+use anyhow::Result;
+use num_format::{Locale, ToFormattedString};
+use std::fmt::Write as _;
+use std::sync::Arc;
+
+/// record the outcome of the generation being shown, if there is one
+pub(crate) fn resolve_generation(ctx: &mut AppContext, outcome: ledger::Outcome) {
+    if let Some(id) = ctx.generation_id.take()
+        && let Err(e) = ledger::resolve(&ctx.config, &id, outcome)
+    {
+        warning!("{}", e);
+    }
+}
+
+/// check the projected cost against the budgets before sending anything, and
+/// show the spinner; None if the user aborted or generation was refused
+///
+/// `pending` is the projected cost of generations which haven't been recorded
+/// in the ledger yet
+pub(crate) fn start_progress(
+    ctx: &AppContext,
+    estimate: &claude::Estimate,
+    pending: f64,
+    input: &mut dyn Input,
+    summary: &str,
+) -> Result<Option<Progress>> {
+    if estimate.call_cost.is_some() {
+        let spent_today = pending
+            + ledger::spent_today(&ctx.config).unwrap_or_else(|e| {
+                warning!("{}", e);
+                0.0
+            });
+        if let Some(reason) = over_budget(&ctx.config, estimate, spent_today) {
+            if ctx.config.refuse_over_budget {
+                error!("{}, not generating", reason);
+                return Ok(None);
+            }
+            warning!("{}", reason);
+            if input.prompt(&["continue", "abort"])? == "a" {
+                return Ok(None);
+            }
+        }
+    }
+
+    Ok(Some(if ctx.debug_prompt {
+        Progress::hidden()
+    } else {
+        Progress::start(format!(
+            "generating commit description from {summary} (~{} tokens)",
+            estimate.input_tokens.to_formatted_string(&Locale::en)
+        ))
+    }))
+}
+
+/// append generated messages to the ledger, returning the generation's id
+pub(crate) fn record_usage(
+    ctx: &AppContext,
+    changeset: &ChangeSet,
+    generated: &[claude::ClaudeResponse],
+) -> String {
+    let id = ledger::new_id();
+    let timestamp = ledger::now();
+    let entries: Vec<_> = generated
+        .iter()
+        .map(|g| ledger::Entry {
+            id: id.clone(),
+            timestamp,
+            repo: changeset.repo.display().to_string(),
+            backend: g.method.clone(),
+            model: g.model.clone(),
+            input_tokens: g.input_tokens,
+            output_tokens: g.output_tokens,
+            cache_read_tokens: g.cache_read_tokens,
+            cache_write_tokens: g.cache_write_tokens,
+            cost: g.cost,
+            outcome: None,
+        })
+        .collect();
+    if let Err(e) = ledger::record(&ctx.config, &entries) {
+        warning!("{}", e);
+    }
+    id
+}
+
+/// reason the projected cost would exceed a budget, if it would; each call
+/// is compared with the per-call budget, and all of them with the daily one
+fn over_budget(config: &Config, estimate: &claude::Estimate, spent_today: f64) -> Option<String> {
+    let (Some(call_cost), Some(cost)) = (estimate.call_cost, estimate.total_cost()) else {
+        return None;
+    };
+    if let Some(budget) = config.budget_per_call
+        && call_cost > budget
+    {
+        return Some(format!(
+            "projected cost ${call_cost:.4} exceeds the per-call budget of ${budget:.4}"
+        ));
+    }
+    if let Some(budget) = config.budget_per_day
+        && spent_today + cost > budget
+    {
+        return Some(format!(
+            "projected cost ${cost:.4} would exceed the daily budget of ${budget:.4} \
+             (${spent_today:.4} spent today)"
+        ));
+    }
+    None
+}
+
+/// result of `sized_changes`
+pub(crate) enum SizedChanges {
+    /// the changes, with as much context as fits
+    Changes(ChangeSet),
+    /// there are no changes
+    Empty,
+    /// the diff is too large, or the user chose not to send it, and the
+    /// reason has been shown
+    Skipped,
+}
+
+/// get changes with the configured context, or less if the diff is large,
+/// asking the user whether to continue if it's still large
+pub(crate) fn sized_changes(
+    ctx: &mut AppContext,
+    input: &mut dyn Input,
+    get_changes: impl Fn(u32) -> Result<Option<ChangeSet>>,
+) -> Result<SizedChanges> {
+    loop {
+        let Some(cs) = get_changes(ctx.context_lines)? else {
+            return Ok(SizedChanges::Empty);
+        };
+        let diff_size = cs.diff.len();
+        let warning_bytes = ctx.config.diff_size_warning_bytes;
+        let maximum_bytes = ctx.config.diff_size_maximum_bytes;
+
+        if diff_size <= warning_bytes {
+            // diff is acceptable size, use it
+            return Ok(SizedChanges::Changes(cs));
+        }
+        if ctx.context_lines > LESS_CONTEXT {
+            // diff is too large, try with less context
+            ctx.context_lines = LESS_CONTEXT;
+            continue;
+        }
+
+        let diff_size_str = diff_size.to_formatted_string(&Locale::en);
+
+        // already tried with less context, check maximum and warn
+        if diff_size > maximum_bytes {
+            error!(
+                "diff is too large ({diff_size_str} chars, max {}k)",
+                maximum_bytes / 1024
+            );
+            return Ok(SizedChanges::Skipped);
+        }
+        warning!("diff is large ({diff_size_str} chars), this may use many tokens");
+        if input.prompt(&["continue", "abort"])? == "a" {
+            return Ok(SizedChanges::Skipped);
+        }
+        return Ok(SizedChanges::Changes(cs));
+    }
+}
+
+/// generate and refine the commit description with the user, returning the
+/// accepted description, or None if the user aborted
+pub(crate) fn process_changes(
+    ctx: &mut AppContext,
+    changeset: &ChangeSet,
+    input: &mut dyn Input,
+) -> Result<Option<String>> {
+    let changeset = Arc::new(changeset.clone());
+    let mut speculative = Vec::new();
+    let result = refine(ctx, &changeset, input, &mut speculative);
+    for job in speculative {
+        discard(ctx, &changeset, job);
+    }
+    let unused_cost = std::mem::take(&mut ctx.unused_cost);
+    if unused_cost > 0.0 {
+        status!("unused speculative generations cost ${unused_cost:.4} USD");
+    }
+    result
+}
+
+/// the interactive loop of `process_changes`
+fn refine(
+    ctx: &mut AppContext,
+    changeset: &Arc<ChangeSet>,
+    input: &mut dyn Input,
+    speculative: &mut Vec<Job>,
+) -> Result<Option<String>> {
+    loop {
+        // switch to a smarter model when rerolling
+        if ctx.manual_reroll_count > 0 || ctx.auto_reroll_count > 0 {
+            ctx.model = ModelTier::Smart;
+        }
+
+        // regenerate commit desc, if required
+        let mut cancelled = false;
+        if ctx.regenerate {
+            match generate(ctx, changeset, input, speculative)? {
+                Generated::Messages(id, descs) => {
+                    resolve_generation(ctx, ledger::Outcome::Rerolled);
+                    ctx.generation_id = Some(id);
+                    accept_generated(ctx, descs);
+                }
+                Generated::Nothing => {}
+                Generated::Cancelled => cancelled = true,
+            }
+        }
+        ctx.model = ModelTier::Fast;
+        ctx.regenerate = true;
+        ctx.think_hard = false;
+
+        // display commit info
+        display_commit_info(ctx, changeset);
+
+        // auto-reroll long lines (claude frequently ignores the 72 char limit)
+        // but only if the description was not user-edited, or the user just
+        // cancelled generation
+        // when choosing between candidates, only reroll if none of them fit
+        if !ctx.user_edited && !cancelled {
+            let max_line_length = ctx.config.max_line_length;
+            let too_long = |desc: &String| {
+                desc.lines()
+                    .any(|line| line.chars().count() > max_line_length)
+            };
+            let any_line_too_long = if ctx.candidates.is_empty() {
+                too_long(&ctx.commit_description)
+            } else {
+                ctx.candidates.iter().all(too_long)
+            };
+            if any_line_too_long {
+                let message = format!(
+                    "commit message {} longer than {} chars",
+                    if ctx.commit_description.lines().count() > 1 {
+                        "has lines"
+                    } else {
+                        "is"
+                    },
+                    max_line_length
+                );
+                if ctx.auto_reroll_count >= MAX_AUTO_REROLLS {
+                    error!(
+                        "{} (not auto-rerolling after {} attempts)",
+                        message, MAX_AUTO_REROLLS
+                    );
+                } else {
+                    error!("{}, rerolling...", message);
+                    ctx.auto_reroll_count += 1;
+                    ctx.think_hard = true;
+                    continue;
+                }
+            }
+            ctx.auto_reroll_count = 0;
+        }
+
+        // display warnings
+        if ctx.candidates.is_empty() && ctx.commit_description.to_lowercase().contains("claude") {
+            warning!("warning: commit desc contains a reference to Claude");
+        }
+        if ctx.candidates.is_empty() && !ctx.multi_line && ctx.commit_description.contains('\n') {
+            warning!("warning: commit message contains multiple lines");
+        }
+
+        // prompt user and handle action; candidates are chosen by number
+        // before the chosen message can be committed
+        let numbers: Vec<String> = (1..=ctx.candidates.len()).map(|n| n.to_string()).collect();
+        let mut options: Vec<&str> = numbers.iter().map(String::as_str).collect();
+        if options.is_empty() {
+            options.push("YES");
+        }
+        options.extend([
+            "no",
+            "reroll",
+            if ctx.multi_line { "short" } else { "long" },
+        ]);
+        if ctx.candidates.is_empty() {
+            options.push("edit");
+        }
+        options.push("prompt");
+
+        // while the user reads, generate what they are likely to ask for next
+        if !ctx.debug_prompt && !ctx.debug_response {
+            speculate(ctx, changeset, speculative);
+        }
+
+        let action = input.prompt(&options)?;
+        match handle_user_action(&action, ctx, input)? {
+            UserAction::Commit => {
+                let outcome = if ctx.user_edited {
+                    ledger::Outcome::Edited
+                } else {
+                    ledger::Outcome::Accepted
+                };
+                resolve_generation(ctx, outcome);
+                return Ok(Some(ctx.commit_description.clone()));
+            }
+            UserAction::Exit => {
+                resolve_generation(ctx, ledger::Outcome::Rejected);
+                return Ok(None);
+            }
+            UserAction::Reroll => {
+                ctx.think_hard = true;
+                ctx.manual_reroll_count += 1;
+            }
+            UserAction::Continue => {
+                ctx.regenerate = false;
+                ctx.manual_reroll_count = 0;
+            }
+        }
+    }
+}
+
+enum UserAction {
+    Commit,
+    Exit,
+    Reroll,
+    Continue,
+}
+
+/// result of `generate`
+enum Generated {
+    /// the generation's ledger id and the generated descriptions
+    Messages(String, Vec<String>),
+    /// nothing was generated, and the reason has been shown
+    Nothing,
+    /// the user cancelled generation
+    Cancelled,
+}
+
+/// show newly generated descriptions, dropping empty ones
+fn accept_generated(ctx: &mut AppContext, descs: Vec<String>) {
+    let descs: Vec<String> = descs
+        .into_iter()
+        .filter(|desc| !desc.trim().is_empty())
+        .collect();
+    if descs.is_empty() {
+        warning!("generated description is empty, using fallback");
+    } else {
+        ctx.commit_description.clone_from(&descs[0]);
+        ctx.candidates = if descs.len() > 1 { descs } else { Vec::new() };
+        ctx.user_edited = false;
+    }
+}
+
+/// generate commit descriptions with spinner; several candidates are
+/// generated in parallel if configured
+///
+/// a matching speculative generation is used if there is one.  generation
+/// runs in the background so the user can cancel it
+fn generate(
+    ctx: &mut AppContext,
+    changeset: &Arc<ChangeSet>,
+    input: &mut dyn Input,
+    speculative: &mut Vec<Job>,
+) -> Result<Generated> {
+    let file_count = changeset.files.len();
+    let summary = format!(
+        "{} [{} {}]",
+        changeset.source(),
+        file_count,
+        if file_count == 1 { "file" } else { "files" }
+    );
+
+    let (job, progress) = if let Some(index) = speculative
+        .iter()
+        .position(|job| job.matches(ctx, changeset))
+    {
+        let job = speculative.swap_remove(index);
+        let progress = if job.is_finished() {
+            Progress::hidden()
+        } else {
+            Progress::start(format!("generating commit description from {summary}"))
+        };
+        (job, progress)
+    } else {
+        // speculative generations will be paid for whether or not they're used
+        let estimate = claude::estimate(ctx, changeset);
+        let pending = speculative.iter().map(Job::projected_cost).sum();
+        let Some(progress) = start_progress(ctx, &estimate, pending, input, &summary)? else {
+            return Ok(Generated::Nothing);
+        };
+        (Job::start(ctx, changeset, progress.clone()), progress)
+    };
+
+    // stop the job if the user cancels, or we can't tell
+    let waited = input.wait_for_cancel(&|| job.is_finished());
+    if !matches!(waited, Ok(false)) {
+        job.cancel();
+    }
+    let output = job.join();
+
+    progress.finish();
+    for warning in output.warnings {
+        warning!(warning);
+    }
+
+    if waited? {
+        warning!("generation cancelled");
+        return Ok(Generated::Cancelled);
+    }
+
+    let mut generated = Vec::new();
+    for result in output.results {
+        match result {
+            Ok(res) => generated.push(res),
+            Err(e) => error!("{}", e),
+        }
+    }
+
+    // record usage; the outcome is recorded once the user decides
+    let id = record_usage(ctx, changeset, &generated);
+
+    let Some(first) = generated.first() else {
+        return Ok(Generated::Nothing);
+    };
+
+    let total =
+        |tokens: fn(&claude::ClaudeResponse) -> u64| -> u64 { generated.iter().map(tokens).sum() };
+    let mut details = format!(
+        "{} {}/{} tokens",
+        first.method,
+        total(|g| g.input_tokens).to_formatted_string(&Locale::en),
+        total(|g| g.output_tokens).to_formatted_string(&Locale::en),
+    );
+    let thinking_tokens = total(|g| g.thinking_tokens);
+    if thinking_tokens > 0 {
+        let _ = write!(
+            details,
+            " (~{} thinking)",
+            thinking_tokens.to_formatted_string(&Locale::en)
+        );
+    }
+    let cache_read_tokens = total(|g| g.cache_read_tokens);
+    if cache_read_tokens > 0 {
+        let _ = write!(
+            details,
+            ", {} cached",
+            cache_read_tokens.to_formatted_string(&Locale::en)
+        );
+    }
+    let _ = write!(details, ", {}", first.model.to_lowercase());
+    if generated.len() > 1 {
+        let _ = write!(details, ", {} candidates", generated.len());
+    }
+    // include what discarded speculative generations cost since the last one
+    let unused_cost = std::mem::take(&mut ctx.unused_cost);
+    let cost = generated.iter().map(|g| g.cost).sum::<Option<f64>>();
+    if let Some(cost) = cost.or((unused_cost > 0.0).then_some(0.0)) {
+        let _ = write!(details, ", ${:.4} USD", cost + unused_cost);
+        if unused_cost > 0.0 {
+            let _ = write!(details, " (${unused_cost:.4} unused speculation)");
+        }
+    }
+    status!("{} ({})", summary, details);
+
+    // drop duplicate candidates
+    let mut messages: Vec<String> = Vec::new();
+    for res in generated {
+        if !messages.contains(&res.message) {
+            messages.push(res.message);
+        }
+    }
+    Ok(Generated::Messages(id, messages))
+}
+
+/// start generating the messages the user is likely to ask for next, and
+/// discard speculative generations which no longer match
+///
+/// speculative generations which would exceed a budget are skipped, as the
+/// user can't be asked to confirm them
+fn speculate(ctx: &mut AppContext, changeset: &Arc<ChangeSet>, jobs: &mut Vec<Job>) {
+    let wanted = background::speculative(ctx);
+
+    let (keep, stale): (Vec<_>, Vec<_>) = jobs
+        .drain(..)
+        .partition(|job| wanted.iter().any(|next| job.matches(next, changeset)));
+    for job in stale {
+        discard(ctx, changeset, job);
+    }
+    *jobs = keep;
+
+    for next in wanted {
+        if jobs.iter().any(|job| job.matches(&next, changeset)) {
+            continue;
+        }
+        let estimate = claude::estimate(&next, changeset);
+        if estimate.call_cost.is_some() {
+            let pending: f64 = jobs.iter().map(Job::projected_cost).sum();
+            let spent_today = pending + ledger::spent_today(&ctx.config).unwrap_or(0.0);
+            if over_budget(&ctx.config, &estimate, spent_today).is_some() {
+                continue;
+            }
+        }
+        jobs.push(Job::start(&next, changeset, Progress::hidden()));
+    }
+}
+
+/// drop a speculative generation the user didn't ask for, recording its
+/// usage if it finished, or cancelling it if not; its warnings and errors
+/// are of no interest
+fn discard(ctx: &mut AppContext, changeset: &ChangeSet, job: Job) {
+    if job.is_finished() {
+        let generated: Vec<_> = job
+            .join()
+            .results
+            .into_iter()
+            .filter_map(Result::ok)
+            .collect();
+        if !generated.is_empty() {
+            record_usage(ctx, changeset, &generated);
+            ctx.unused_cost += generated.iter().filter_map(|g| g.cost).sum::<f64>();
+        }
+    } else {
+        job.cancel();
+    }
+}
+
+/// display commit description and files
+fn display_commit_info(ctx: &AppContext, changeset: &ChangeSet) {
+    use colored::Colorize;
+    use std::io::{self, Write};
+
+    /// print text with "claude" (case insensitive) highlighted in yellow
+    fn print_with_claude_highlighted(text: &str) {
+        let lower = text.to_lowercase();
+        let mut last_end = 0;
+
+        while let Some(pos) = lower[last_end..].find("claude") {
+            let absolute_pos = last_end + pos;
+
+            // print the part before "claude"
+            let before = &text[last_end..absolute_pos];
+            if !before.is_empty() {
+                let _ = write!(io::stdout(), "{before}");
+            }
+
+            // print "claude" in yellow
+            let claude_end = absolute_pos + "claude".len();
+            let claude_part = &text[absolute_pos..claude_end];
+            let _ = write!(io::stdout(), "{}", claude_part.yellow());
+
+            last_end = claude_end;
+        }
+
+        // print the remaining part
+        if last_end < text.len() {
+            let remaining = &text[last_end..];
+            let _ = write!(io::stdout(), "{remaining}");
+        }
+    }
+
+    // print each line of commit description, highlighting chars beyond max-line-length in red
+    // and highlighting "claude" in yellow (red overrides yellow for long lines)
+    let max_line_length = ctx.config.max_line_length;
+    let print_description = |description: &str, number: Option<usize>| {
+        for (i, line) in description.lines().enumerate() {
+            // candidates are numbered, with following lines indented to match
+            match number {
+                Some(n) if i == 0 => {
+                    let _ = write!(io::stdout(), "{} ", format!("{n})").cyan());
+                }
+                Some(_) => {
+                    let _ = write!(io::stdout(), "   ");
+                }
+                None => {}
+            }
+            // the limit is in characters, and generated text may not be ascii
+            if let Some((end, _)) = line.char_indices().nth(max_line_length) {
+                let (first_part, rest) = line.split_at(end);
+                print_with_claude_highlighted(first_part);
+                let _ = write!(io::stdout(), "{}", rest.red());
+                let _ = writeln!(io::stdout());
+            } else {
+                print_with_claude_highlighted(line);
+                let _ = writeln!(io::stdout());
+            }
+        }
+    };
+    let _ = writeln!(io::stdout());
+    if ctx.candidates.is_empty() {
+        print_description(&ctx.commit_description, None);
+    } else {
+        for (i, candidate) in ctx.candidates.iter().enumerate() {
+            if i > 0 && ctx.multi_line {
+                let _ = writeln!(io::stdout());
+            }
+            print_description(candidate, Some(i + 1));
+        }
+    }
+    let _ = writeln!(io::stdout());
+
+    status!("files:");
+    // staged and unstaged changes are committed together, so show which is which
+    let files: Vec<&FileChange> = changeset.files.iter().collect();
+    display_files(&files, changeset.scope != Scope::Auto);
+
+    let left_out: Vec<&FileChange> = changeset
+        .unselected
+        .iter()
+        .chain(&changeset.excluded)
+        .collect();
+    if !left_out.is_empty() {
+        warning!("not committed:");
+        display_files(&left_out, true);
+    }
+
+    info!();
+}
+
+/// list files with their status, optionally noting where their changes are
+fn display_files(files: &[&FileChange], show_source: bool) {
+    for file in files.iter().take(MAX_FILES_TO_SHOW) {
+        let mut notes = Vec::new();
+        if show_source {
+            notes.push(file.source.label());
+        }
+        match file.file_type {
+            FileType::Normal => {}
+            FileType::Binary => notes.push("binary"),
+            FileType::Generated => notes.push("generated"),
+            FileType::Vendored => notes.push("vendored"),
+        }
+        let suffix = if notes.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", notes.join(", "))
+        };
+        if let Some(old_path) = &file.old_path {
+            // show renames as "old_path → new_path"
+            info!(
+                "{} {} → {}{}",
+                status_char(file.status),
+                old_path,
+                file.path,
+                suffix
+            );
+        } else {
+            info!("{} {}{}", status_char(file.status), file.path, suffix);
+        }
+    }
+
+    // show count of remaining files if there are more than MAX_FILES_TO_SHOW
+    if files.len() > MAX_FILES_TO_SHOW {
+        let remaining = files.len() - MAX_FILES_TO_SHOW;
+        info!("(+{} more)", remaining);
+    }
+}
+
+/// handle user action and return what to do next
+fn handle_user_action(
+    action: &str,
+    ctx: &mut AppContext,
+    input: &mut dyn Input,
+) -> Result<UserAction> {
+    match action {
+        "y" => Ok(UserAction::Commit),
+        "n" => Ok(UserAction::Exit),
+        "r" => Ok(UserAction::Reroll),
+        "s" => {
+            ctx.multi_line = false;
+            ctx.commit_description = first_line(&ctx.commit_description);
+            for candidate in &mut ctx.candidates {
+                *candidate = first_line(candidate);
+            }
+            Ok(UserAction::Continue)
+        }
+        "l" => {
+            ctx.multi_line = true;
+            Ok(UserAction::Reroll)
+        }
+        "e" => {
+            ctx.commit_description = if ctx.multi_line {
+                input.edit_multi_line(&ctx.commit_description)?
+            } else {
+                info!("");
+                input.edit_one_line(&ctx.commit_description)?
+            };
+            if ctx.commit_description.trim().is_empty() {
+                return Ok(UserAction::Exit);
+            }
+            ctx.user_edited = true;
+            Ok(UserAction::Continue)
+        }
+        "p" => {
+            status!("provide extra claude prompt context:");
+            let old_prompt_extra = ctx.prompt_extra.clone();
+            ctx.prompt_extra = input.edit_one_line(&ctx.prompt_extra)?;
+            if ctx.prompt_extra == old_prompt_extra {
+                Ok(UserAction::Continue)
+            } else {
+                Ok(UserAction::Reroll)
+            }
+        }
+        _ => {
+            // choose a candidate by number
+            if let Some(candidate) = action
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| ctx.candidates.get(n.wrapping_sub(1)))
+            {
+                ctx.commit_description.clone_from(candidate);
+                ctx.candidates.clear();
+            }
+            Ok(UserAction::Continue)
+        }
+    }
+}
+
+/// the first line of a message
+pub(crate) fn first_line(text: &str) -> String {
+    text.lines().next().unwrap_or("").to_string()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,796 @@
+use super::*;
+use crate::constants::MAX_LINE_LENGTH;
+use crate::{context, git, provider, reword, select, split, ui};
+use std::collections::VecDeque;
+use std::fs;
+use tempfile::TempDir;
+
+/// scripted user input; each prompt or edit consumes the next response
+///
+/// a "cancel" response cancels generation, and a "wait" response pauses
+/// before the following prompt is answered
+struct Script {
+    responses: VecDeque<&'static str>,
+    /// for each generation, whether it had finished before it was waited for
+    instant: Vec<bool>,
+}
+
+impl Script {
+    fn new(responses: &[&'static str]) -> Self {
+        Self {
+            responses: responses.iter().copied().collect(),
+            instant: Vec::new(),
+        }
+    }
+
+    fn next(&mut self) -> String {
+        self.responses
+            .pop_front()
+            .expect("unexpected prompt")
+            .to_string()
+    }
+}
+
+impl ui::Input for Script {
+    fn prompt(&mut self, options: &[&str]) -> Result<String> {
+        if self.responses.front() == Some(&"wait") {
+            self.responses.pop_front();
+            std::thread::sleep(std::time::Duration::from_millis(500));
+        }
+        let response = self.next();
+        assert!(
+            options
+                .iter()
+                .any(|o| o.to_lowercase().starts_with(&response)),
+            "{response} is not one of {options:?}"
+        );
+        Ok(response)
+    }
+
+    fn edit_one_line(&mut self, _line: &str) -> Result<String> {
+        Ok(self.next())
+    }
+
+    fn edit_multi_line(&mut self, _text: &str) -> Result<String> {
+        Ok(self.next())
+    }
+
+    fn wait_for_cancel(&mut self, done: &dyn Fn() -> bool) -> Result<bool> {
+        self.instant.push(done());
+        if self.responses.front() == Some(&"cancel") {
+            self.responses.pop_front();
+            return Ok(true);
+        }
+        while !done() {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        Ok(false)
+    }
+}
+
+/// helper to create a repository with an uncommitted file
+fn setup_repo() -> (TempDir, ChangeSet) {
+    let repo_dir = TempDir::new().unwrap();
+    git2::Repository::init(repo_dir.path()).unwrap();
+    fs::write(repo_dir.path().join("hello.txt"), "hello\n").unwrap();
+    let changeset = git::get_changes(repo_dir.path(), 3, git::Scope::Auto, &[], &[], None)
+        .unwrap()
+        .unwrap();
+    (repo_dir, changeset)
+}
+
+/// helper to create a context which replays fixtures from `fixtures`,
+/// recording them from `command` (given the model tier) if set
+fn replay_context(fixtures: &TempDir, command: Option<&str>) -> context::AppContext {
+    let mut config = Config {
+        ledger_file: Some(fixtures.path().join("ledger.jsonl")),
+        ..Config::default()
+    };
+    config.replay.dir = Some(fixtures.path().join("replay"));
+    if let Some(command) = command {
+        config.replay.record = Some("command".to_string());
+        config.command.command = Some(command.to_string());
+    }
+    let provider = provider::create("replay", &config).unwrap();
+    context::AppContext::new(config, provider, false, false)
+}
+
+/// command which responds with `fast` or `smart` depending on the model tier
+fn tiered_command(fast: &str, smart: &str) -> String {
+    format!(
+        "cat > /dev/null; if [ \"$GIT_AUTO_COMMIT_MODEL_TIER\" = fast ]; \
+         then echo '{fast}'; else echo '{smart}'; fi"
+    )
+}
+
+#[test]
+fn test_accept() {
+    let (_repo_dir, changeset) = setup_repo();
+    let fixtures = TempDir::new().unwrap();
+    let command = tiered_command("add hello", "unused");
+    let mut ctx = replay_context(&fixtures, Some(&command));
+
+    let result = process_changes(&mut ctx, &changeset, &mut Script::new(&["y"])).unwrap();
+    assert_eq!(result.as_deref(), Some("add hello"));
+
+    // the response was recorded, and is replayed without the command
+    assert_eq!(
+        fs::read_dir(fixtures.path().join("replay"))
+            .unwrap()
+            .count(),
+        1
+    );
+    let mut ctx = replay_context(&fixtures, None);
+    let result = process_changes(&mut ctx, &changeset, &mut Script::new(&["y"])).unwrap();
+    assert_eq!(result.as_deref(), Some("add hello"));
+}
+
+#[test]
+fn test_abort() {
+    let (_repo_dir, changeset) = setup_repo();
+    let fixtures = TempDir::new().unwrap();
+    let command = tiered_command("add hello", "unused");
+    let mut ctx = replay_context(&fixtures, Some(&command));
+
+    let result = process_changes(&mut ctx, &changeset, &mut Script::new(&["n"])).unwrap();
+    assert_eq!(result, None);
+    let entries = ledger::read(&ctx.config).unwrap();
+    assert_eq!(entries[0].outcome, Some(ledger::Outcome::Rejected));
+    assert_eq!(entries[0].repo, changeset.repo.display().to_string());
+}
+
+#[test]
+fn test_reroll() {
+    let (_repo_dir, changeset) = setup_repo();
+    let fixtures = TempDir::new().unwrap();
+    let command = tiered_command("add file", "add hello file");
+    let mut ctx = replay_context(&fixtures, Some(&command));
+
+    // rerolling uses the smart model
+    let result = process_changes(&mut ctx, &changeset, &mut Script::new(&["r", "y"])).unwrap();
+    assert_eq!(result.as_deref(), Some("add hello file"));
+    assert_eq!(
+        fs::read_dir(fixtures.path().join("replay"))
+            .unwrap()
+            .count(),
+        2
+    );
+    // both generations are in the ledger, with their outcomes
+    let outcomes: Vec<_> = ledger::read(&ctx.config)
+        .unwrap()
+        .iter()
+        .map(|entry| entry.outcome)
+        .collect();
+    assert_eq!(
+        outcomes,
+        [
+            Some(ledger::Outcome::Rerolled),
+            Some(ledger::Outcome::Accepted)
+        ]
+    );
+}
+
+#[test]
+fn test_cancel() {
+    let (_repo_dir, changeset) = setup_repo();
+    let fixtures = TempDir::new().unwrap();
+    let command = "cat > /dev/null; if [ \"$GIT_AUTO_COMMIT_MODEL_TIER\" = fast ]; \
+                   then echo 'add hello'; else sleep 10; fi";
+    let mut ctx = replay_context(&fixtures, Some(command));
+
+    // cancelling the reroll kills the command and keeps the first message
+    let start = std::time::Instant::now();
+    let result = process_changes(
+        &mut ctx,
+        &changeset,
+        &mut Script::new(&["r", "cancel", "y"]),
+    )
+    .unwrap();
+    assert_eq!(result.as_deref(), Some("add hello"));
+    assert!(start.elapsed().as_secs() < 10);
+
+    // the cancelled generation was neither recorded nor replayed
+    assert_eq!(
+        fs::read_dir(fixtures.path().join("replay"))
+            .unwrap()
+            .count(),
+        1
+    );
+    let entries = ledger::read(&ctx.config).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].outcome, Some(ledger::Outcome::Accepted));
+}
+
+#[test]
+fn test_speculate_long() {
+    let (_repo_dir, changeset) = setup_repo();
+    let fixtures = TempDir::new().unwrap();
+    let command = tiered_command("add hello", "add hello file");
+    let mut ctx = replay_context(&fixtures, Some(&command));
+    ctx.config.speculate_long = true;
+
+    // the long message was generated while the short one was shown
+    let mut script = Script::new(&["wait", "l", "y"]);
+    let result = process_changes(&mut ctx, &changeset, &mut script).unwrap();
+    assert_eq!(result.as_deref(), Some("add hello file"));
+    assert_eq!(script.instant.last(), Some(&true));
+
+    // and is counted once it is used
+    let outcomes: Vec<_> = ledger::read(&ctx.config)
+        .unwrap()
+        .iter()
+        .map(|entry| entry.outcome)
+        .collect();
+    assert_eq!(
+        outcomes,
+        [
+            Some(ledger::Outcome::Rerolled),
+            Some(ledger::Outcome::Accepted)
+        ]
+    );
+}
+
+#[test]
+fn test_speculative_warnings() {
+    let (_repo_dir, changeset) = setup_repo();
+    let fixtures = TempDir::new().unwrap();
+    let command = tiered_command("add hello", "unused");
+    let ctx = replay_context(&fixtures, Some(&command));
+
+    // warnings are returned with the job's results rather than printed
+    let job = Job::start(&ctx, &Arc::new(changeset), ui::Progress::hidden());
+    let output = job.join();
+    assert_eq!(output.results.len(), 1);
+    assert_eq!(output.results[0].as_ref().unwrap().message, "add hello");
+    assert_eq!(
+        output.warnings,
+        ["claude output did not contain triple backticks"]
+    );
+}
+
+#[test]
+fn test_discarded_cost() {
+    let (_repo_dir, changeset) = setup_repo();
+    let fixtures = TempDir::new().unwrap();
+    let mut config = Config {
+        ledger_file: Some(fixtures.path().join("ledger.jsonl")),
+        ..Config::default()
+    };
+    config.command.command =
+        Some(r#"cat > /dev/null; echo '{"message": "```add hello```", "cost": 0.25}'"#.to_string());
+    config.command.json = true;
+    let provider = provider::create("command", &config).unwrap();
+    let mut ctx = context::AppContext::new(config, provider, false, false);
+
+    // a discarded speculative generation is recorded, and its cost kept to
+    // be shown with the next
+    let changeset = Arc::new(changeset);
+    let job = Job::start(&ctx, &changeset, ui::Progress::hidden());
+    while !job.is_finished() {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    discard(&mut ctx, &changeset, job);
+    assert!((ctx.unused_cost - 0.25).abs() < 1e-9);
+    let entries = ledger::read(&ctx.config).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].cost, Some(0.25));
+}
+
+#[test]
+fn test_auto_reroll_long_lines() {
+    let (_repo_dir, changeset) = setup_repo();
+    let fixtures = TempDir::new().unwrap();
+    let long = "x".repeat(MAX_LINE_LENGTH + 1);
+    let command = tiered_command(&long, "add hello");
+    let mut ctx = replay_context(&fixtures, Some(&command));
+
+    // the long message is rerolled without prompting the user
+    let result = process_changes(&mut ctx, &changeset, &mut Script::new(&["y"])).unwrap();
+    assert_eq!(result.as_deref(), Some("add hello"));
+}
+
+#[test]
+fn test_auto_reroll_limit() {
+    let (_repo_dir, changeset) = setup_repo();
+    let fixtures = TempDir::new().unwrap();
+    let long = "x".repeat(MAX_LINE_LENGTH + 1);
+    let command = tiered_command(&long, &long);
+    let mut ctx = replay_context(&fixtures, Some(&command));
+
+    // rerolling stops after the maximum attempts, leaving the user to decide
+    let result = process_changes(&mut ctx, &changeset, &mut Script::new(&["y"])).unwrap();
+    assert_eq!(result.as_deref(), Some(long.as_str()));
+}
+
+/// command which responds with the message for its candidate and model tier
+fn candidates_command(fast: &[&str], smart: &[&str]) -> String {
+    let mut responses = String::new();
+    for (tier, messages) in [("fast", fast), ("smart", smart)] {
+        for (i, message) in messages.iter().enumerate() {
+            let _ = write!(responses, "{tier}{}) echo '{message}';; ", i + 1);
+        }
+    }
+    format!(
+        "prompt=$(cat); n=1; for i in 2 3 4 5; do case \"$prompt\" in \
+         *\"alternative $i of\"*) n=$i;; esac; done; \
+         case \"$GIT_AUTO_COMMIT_MODEL_TIER$n\" in {responses}esac"
+    )
+}
+
+#[test]
+fn test_candidates_choose() {
+    let (_repo_dir, changeset) = setup_repo();
+    let fixtures = TempDir::new().unwrap();
+    let command = candidates_command(&["add hello", "add hello.txt", "create hello"], &[]);
+    let mut ctx = replay_context(&fixtures, Some(&command));
+    ctx.config.candidates = 3;
+
+    // a candidate is chosen by number, then confirmed
+    let result = process_changes(&mut ctx, &changeset, &mut Script::new(&["2", "y"])).unwrap();
+    assert_eq!(result.as_deref(), Some("add hello.txt"));
+
+    // the candidates which weren't chosen share the generation's outcome
+    let outcomes: Vec<_> = ledger::read(&ctx.config)
+        .unwrap()
+        .iter()
+        .map(|entry| entry.outcome)
+        .collect();
+    assert_eq!(outcomes, [Some(ledger::Outcome::Accepted); 3]);
+}
+
+#[test]
+fn test_candidates_duplicates() {
+    let (_repo_dir, changeset) = setup_repo();
+    let fixtures = TempDir::new().unwrap();
+    let command = candidates_command(&["add hello", "add hello", "create hello"], &[]);
+    let mut ctx = replay_context(&fixtures, Some(&command));
+    ctx.config.candidates = 3;
+
+    // the duplicate is dropped, leaving two to choose between
+    let result = process_changes(&mut ctx, &changeset, &mut Script::new(&["2", "y"])).unwrap();
+    assert_eq!(result.as_deref(), Some("create hello"));
+
+    // if every candidate is the same, there is nothing to choose
+    let fixtures = TempDir::new().unwrap();
+    let command = candidates_command(&["add hello", "add hello"], &[]);
+    let mut ctx = replay_context(&fixtures, Some(&command));
+    ctx.config.candidates = 2;
+    let result = process_changes(&mut ctx, &changeset, &mut Script::new(&["y"])).unwrap();
+    assert_eq!(result.as_deref(), Some("add hello"));
+}
+
+#[test]
+fn test_candidates_auto_reroll() {
+    let (_repo_dir, changeset) = setup_repo();
+    let long = "x".repeat(MAX_LINE_LENGTH + 1);
+    let long2 = "y".repeat(MAX_LINE_LENGTH + 1);
+
+    // a candidate which fits is enough to not reroll
+    let fixtures = TempDir::new().unwrap();
+    let command = candidates_command(&[&long, "add hello"], &["unused", "unused"]);
+    let mut ctx = replay_context(&fixtures, Some(&command));
+    ctx.config.candidates = 2;
+    let result = process_changes(&mut ctx, &changeset, &mut Script::new(&["2", "y"])).unwrap();
+    assert_eq!(result.as_deref(), Some("add hello"));
+
+    // but if none fit, the whole set is rerolled without prompting the user
+    let fixtures = TempDir::new().unwrap();
+    let command = candidates_command(&[&long, &long2], &["add hello", "create hello"]);
+    let mut ctx = replay_context(&fixtures, Some(&command));
+    ctx.config.candidates = 2;
+    let result = process_changes(&mut ctx, &changeset, &mut Script::new(&["1", "y"])).unwrap();
+    assert_eq!(result.as_deref(), Some("add hello"));
+}
+
+#[test]
+fn test_candidates_reroll() {
+    let (_repo_dir, changeset) = setup_repo();
+    let fixtures = TempDir::new().unwrap();
+    let command = candidates_command(&["add hello", "create hello"], &["add file", "create file"]);
+    let mut ctx = replay_context(&fixtures, Some(&command));
+    ctx.config.candidates = 2;
+
+    // rerolling replaces every candidate
+    let result = process_changes(&mut ctx, &changeset, &mut Script::new(&["r", "2", "y"])).unwrap();
+    assert_eq!(result.as_deref(), Some("create file"));
+    assert_eq!(
+        fs::read_dir(fixtures.path().join("replay"))
+            .unwrap()
+            .count(),
+        4
+    );
+    let outcomes: Vec<_> = ledger::read(&ctx.config)
+        .unwrap()
+        .iter()
+        .map(|entry| entry.outcome)
+        .collect();
+    assert_eq!(
+        outcomes,
+        [
+            Some(ledger::Outcome::Rerolled),
+            Some(ledger::Outcome::Rerolled),
+            Some(ledger::Outcome::Accepted),
+            Some(ledger::Outcome::Accepted)
+        ]
+    );
+}
+
+#[test]
+fn test_candidates_non_ascii() {
+    let (_repo_dir, changeset) = setup_repo();
+    let fixtures = TempDir::new().unwrap();
+    let accented = "é".repeat(MAX_LINE_LENGTH / 2 + 1);
+    let long = format!("a{} — ✨", "é".repeat(MAX_LINE_LENGTH));
+    let command = candidates_command(&[&accented, &long], &[]);
+    let mut ctx = replay_context(&fixtures, Some(&command));
+    ctx.config.candidates = 2;
+
+    // lines are measured in characters, so the first fits, and the second is
+    // highlighted from a character boundary
+    let result = process_changes(&mut ctx, &changeset, &mut Script::new(&["1", "y"])).unwrap();
+    assert_eq!(result.as_deref(), Some(accented.as_str()));
+}
+
+#[test]
+fn test_generation_error() {
+    let (_repo_dir, changeset) = setup_repo();
+    let fixtures = TempDir::new().unwrap();
+    let mut ctx = replay_context(&fixtures, Some("cat > /dev/null; exit 1"));
+
+    // the failure is recorded and replayed, falling back to the default
+    // description, which can then be edited
+    let result = process_changes(&mut ctx, &changeset, &mut Script::new(&["y"])).unwrap();
+    assert_eq!(result.as_deref(), Some("bug fixes and/or improvements"));
+
+    let mut ctx = replay_context(&fixtures, None);
+    let result = process_changes(
+        &mut ctx,
+        &changeset,
+        &mut Script::new(&["e", "add hello", "y"]),
+    )
+    .unwrap();
+    assert_eq!(result.as_deref(), Some("add hello"));
+}
+
+#[test]
+fn test_missing_fixture() {
+    let (_repo_dir, changeset) = setup_repo();
+    let fixtures = TempDir::new().unwrap();
+    let mut ctx = replay_context(&fixtures, None);
+
+    let result = process_changes(&mut ctx, &changeset, &mut Script::new(&["n"])).unwrap();
+    assert_eq!(result, None);
+    assert_eq!(ctx.commit_description, "bug fixes and/or improvements");
+}
+
+/// helper to create a context whose estimated cost is $1 per call; the
+/// (unreachable) openai provider is never sent anything
+fn priced_context(fixtures: &TempDir, config: Config) -> context::AppContext {
+    let mut config = Config {
+        ledger_file: Some(fixtures.path().join("ledger.jsonl")),
+        ..config
+    };
+    config.replay.dir = Some(fixtures.path().join("replay"));
+    config.replay.record = Some("openai".to_string());
+    config.openai.base_url = "http://127.0.0.1:9".to_string();
+    config.openai.model_fast = Some("priced".to_string());
+    config.pricing.insert(
+        "priced".to_string(),
+        crate::pricing::Price {
+            input: 1_000_000.0,
+            output: 0.0,
+            cache_read: 0.0,
+            cache_write: 0.0,
+        },
+    );
+    let provider = provider::create("replay", &config).unwrap();
+    context::AppContext::new(config, provider, false, false)
+}
+
+#[test]
+fn test_over_budget_refused() {
+    let (_repo_dir, changeset) = setup_repo();
+    let fixtures = TempDir::new().unwrap();
+    let config = Config {
+        budget_per_call: Some(0.5),
+        refuse_over_budget: true,
+        ..Config::default()
+    };
+    let mut ctx = priced_context(&fixtures, config);
+
+    // nothing is generated, leaving the fallback description
+    let result = process_changes(&mut ctx, &changeset, &mut Script::new(&["y"])).unwrap();
+    assert_eq!(result.as_deref(), Some("bug fixes and/or improvements"));
+}
+
+#[test]
+fn test_over_budget_confirm() {
+    let (_repo_dir, changeset) = setup_repo();
+    let fixtures = TempDir::new().unwrap();
+    let config = Config {
+        budget_per_day: Some(0.5),
+        ..Config::default()
+    };
+    let mut ctx = priced_context(&fixtures, config);
+
+    // the user is asked before generating, and can abort
+    let result = process_changes(&mut ctx, &changeset, &mut Script::new(&["a", "n"])).unwrap();
+    assert_eq!(result, None);
+}
+
+#[test]
+fn test_over_budget_pending() {
+    let (_repo_dir, changeset) = setup_repo();
+    let fixtures = TempDir::new().unwrap();
+    let mut ctx = priced_context(&fixtures, Config::default());
+    let cost = claude::estimate(&ctx, &changeset).total_cost().unwrap();
+    ctx.config.budget_per_day = Some(cost * 1.5);
+
+    // a running speculative generation counts towards the daily budget,
+    // though it isn't in the ledger yet
+    let changeset = Arc::new(changeset);
+    let mut next = ctx.clone();
+    next.multi_line = true;
+    let mut speculative = vec![Job::start(&next, &changeset, ui::Progress::hidden())];
+    let mut script = Script::new(&["a"]);
+    let generated = generate(&mut ctx, &changeset, &mut script, &mut speculative).unwrap();
+    assert!(matches!(generated, Generated::Nothing));
+    assert!(script.responses.is_empty());
+    for job in speculative {
+        job.cancel();
+    }
+}
+
+#[test]
+fn test_over_budget() {
+    let config = Config {
+        budget_per_call: Some(1.0),
+        budget_per_day: Some(5.0),
+        ..Config::default()
+    };
+    let estimate = |call_cost, calls| claude::Estimate {
+        input_tokens: 0,
+        call_cost: Some(call_cost),
+        calls,
+    };
+    assert!(over_budget(&config, &estimate(0.5, 1), 0.0).is_none());
+    assert!(
+        over_budget(&config, &estimate(1.5, 1), 0.0)
+            .unwrap()
+            .contains("per-call")
+    );
+    assert!(
+        over_budget(&config, &estimate(0.5, 1), 4.75)
+            .unwrap()
+            .contains("daily")
+    );
+
+    // candidates are separate calls, which only add up for the daily budget
+    assert!(over_budget(&config, &estimate(0.75, 3), 0.0).is_none());
+    assert!(
+        over_budget(&config, &estimate(0.75, 3), 3.0)
+            .unwrap()
+            .contains("daily")
+    );
+}
+
+#[test]
+fn test_over_budget_thinking() {
+    let (_repo_dir, changeset) = setup_repo();
+    let fixtures = TempDir::new().unwrap();
+    let mut config = Config {
+        api_key: Some("key".to_string()),
+        budget_per_call: Some(0.05),
+        ..Config::default()
+    };
+    config.replay.dir = Some(fixtures.path().join("replay"));
+    config.replay.record = Some("claude-api".to_string());
+    let provider = provider::create("replay", &config).unwrap();
+    let mut ctx = context::AppContext::new(config, provider, false, false);
+    let model_id = ctx.provider.model_id(&ctx).unwrap();
+    ctx.config.pricing.insert(
+        model_id,
+        crate::pricing::Price {
+            input: 0.0,
+            output: 10.0,
+            cache_read: 0.0,
+            cache_write: 0.0,
+        },
+    );
+
+    // a plain call fits the budget, but a reroll may use the whole thinking
+    // budget, which is billed as output
+    assert!(over_budget(&ctx.config, &claude::estimate(&ctx, &changeset), 0.0).is_none());
+    ctx.think_hard = true;
+    assert!(
+        over_budget(&ctx.config, &claude::estimate(&ctx, &changeset), 0.0)
+            .unwrap()
+            .contains("per-call")
+    );
+
+    // unless the provider is asked for thinking in the prompt instead
+    ctx.config.claude_api.thinking = false;
+    ctx.provider = provider::create("replay", &ctx.config).unwrap().into();
+    assert!(over_budget(&ctx.config, &claude::estimate(&ctx, &changeset), 0.0).is_none());
+}
+
+/// helper to create a repository with a committed file with two changed
+/// lines, far enough apart to be separate hunks, and an untracked file
+fn setup_two_hunks() -> TempDir {
+    let repo_dir = TempDir::new().unwrap();
+    let path = repo_dir.path();
+    let repo = git2::Repository::init(path).unwrap();
+    let mut config = repo.config().unwrap();
+    config.set_str("user.name", "Test User").unwrap();
+    config.set_str("user.email", "test@example.com").unwrap();
+
+    let lines: Vec<String> = (1..=20).map(|n| format!("line {n}\n")).collect();
+    fs::write(path.join("a.txt"), lines.concat()).unwrap();
+    let mut index = repo.index().unwrap();
+    index.add_path(std::path::Path::new("a.txt")).unwrap();
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = repo.signature().unwrap();
+    repo.commit(Some("HEAD"), &signature, &signature, "initial", &tree, &[])
+        .unwrap();
+    let mut changed = lines.clone();
+    changed[1] = String::from("changed 2\n");
+    changed[18] = String::from("changed 19\n");
+    fs::write(path.join("a.txt"), changed.concat()).unwrap();
+    fs::write(path.join("b.txt"), "new\n").unwrap();
+    repo_dir
+}
+
+#[test]
+fn test_select() {
+    let repo_dir = setup_two_hunks();
+    let path = repo_dir.path();
+    let changeset = git::get_changes(path, 3, git::Scope::Auto, &[], &[], None)
+        .unwrap()
+        .unwrap();
+
+    // choose the first hunk of a.txt, and not b.txt
+    let mut script = Script::new(&["h", "y", "n", "n"]);
+    let selection = select::choose(path, &changeset, &mut script).unwrap();
+    assert_eq!(selection.files.len(), 1);
+    assert_eq!(selection.files["a.txt"].as_ref().map(Vec::len), Some(1));
+
+    // choosing every hunk chooses the whole file
+    let mut script = Script::new(&["h", "a", "y"]);
+    let selection = select::choose(path, &changeset, &mut script).unwrap();
+    assert_eq!(selection.files["a.txt"], None);
+    assert_eq!(selection.files["b.txt"], None);
+
+    let mut script = Script::new(&["d"]);
+    let result = select::choose(path, &changeset, &mut script);
+    assert_eq!(result.unwrap_err().to_string(), "nothing selected");
+}
+
+#[test]
+fn test_split() {
+    let repo_dir = setup_two_hunks();
+    let path = repo_dir.path();
+    let changeset = git::get_changes(path, 3, git::Scope::Auto, &[], &[], None)
+        .unwrap()
+        .unwrap();
+    let fixtures = TempDir::new().unwrap();
+    let plan = r#"[{"message": "change line 2", "changes": ["a.txt#1"]},
+        {"message": "change line 19 and add b", "changes": ["a.txt#2", "b.txt"]}]"#;
+    let command = format!("cat > /dev/null; echo '```json'; echo '{plan}'; echo '```'");
+    let mut ctx = replay_context(&fixtures, Some(&command));
+
+    let commits = split::plan(&mut ctx, &changeset, &mut Script::new(&["y"]))
+        .unwrap()
+        .unwrap();
+    assert_eq!(commits.len(), 2);
+    split::commit(path, &changeset, &[], &commits).unwrap();
+
+    // each group is committed in order, leaving nothing uncommitted
+    let repo = git2::Repository::open(path).unwrap();
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.summary(), Some("change line 19 and add b"));
+    let first = head.parent(0).unwrap();
+    assert_eq!(first.summary(), Some("change line 2"));
+    let blob = first
+        .tree()
+        .unwrap()
+        .get_path(std::path::Path::new("a.txt"))
+        .unwrap()
+        .to_object(&repo)
+        .unwrap()
+        .peel_to_blob()
+        .unwrap();
+    let contents = String::from_utf8_lossy(blob.content());
+    assert!(contents.contains("changed 2\n") && contents.contains("line 19\n"));
+    assert!(
+        git::get_changes(path, 3, git::Scope::Auto, &[], &[], None)
+            .unwrap()
+            .is_none()
+    );
+
+    let entries = ledger::read(&ctx.config).unwrap();
+    assert_eq!(entries[0].outcome, Some(ledger::Outcome::Accepted));
+}
+
+/// helper to create a repository with a commit adding each file
+fn setup_history(commits: &[(&str, &str, &str)]) -> (TempDir, git2::Repository) {
+    let repo_dir = TempDir::new().unwrap();
+    let path = repo_dir.path();
+    let repo = git2::Repository::init(path).unwrap();
+    let mut config = repo.config().unwrap();
+    config.set_str("user.name", "Test User").unwrap();
+    config.set_str("user.email", "test@example.com").unwrap();
+    let signature = repo.signature().unwrap();
+    let mut parent = None;
+    for (file, contents, message) in commits {
+        fs::write(path.join(file), contents).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(std::path::Path::new(file)).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        let oid = repo
+            .commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                message,
+                &tree,
+                &parents,
+            )
+            .unwrap();
+        parent = Some(repo.find_commit(oid).unwrap());
+    }
+    drop(parent);
+    (repo_dir, repo)
+}
+
+#[test]
+fn test_reword() {
+    let (repo_dir, repo) = setup_history(&[
+        ("a.txt", "hello\n", "initial"),
+        ("b.txt", "hello\n", "wip"),
+        ("c.txt", "hello\n", "wip 2"),
+    ]);
+
+    // the first commit is reworded, the second is declined
+    let fixtures = TempDir::new().unwrap();
+    let command = tiered_command("add b", "unused");
+    let mut ctx = replay_context(&fixtures, Some(&command));
+    let mut script = Script::new(&["y", "n"]);
+    reword::run(&mut ctx, repo_dir.path(), "HEAD~2..", &mut script).unwrap();
+
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.message(), Some("wip 2"));
+    let reworded = head.parent(0).unwrap();
+    assert_eq!(reworded.message(), Some("add b\n"));
+    assert_eq!(reworded.parent(0).unwrap().message(), Some("initial"));
+}
+
+#[test]
+fn test_reword_skip_large() {
+    let large = "hello\n".repeat(400);
+    let too_large = "hello\n".repeat(2000);
+    let (repo_dir, repo) = setup_history(&[
+        ("a.txt", "hello\n", "initial"),
+        ("b.txt", "hello\n", "wip"),
+        ("c.txt", &large, "wip 2"),
+        ("d.txt", &too_large, "wip 3"),
+    ]);
+
+    // aborting on a large diff, or one too large to send, keeps that
+    // commit's message without losing those already accepted
+    let fixtures = TempDir::new().unwrap();
+    let command = tiered_command("add file", "unused");
+    let mut ctx = replay_context(&fixtures, Some(&command));
+    ctx.config.diff_size_warning_bytes = 1024;
+    ctx.config.diff_size_maximum_bytes = 4096;
+    let mut script = Script::new(&["y", "a"]);
+    reword::run(&mut ctx, repo_dir.path(), "HEAD~3..", &mut script).unwrap();
+
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.message(), Some("wip 3"));
+    let skipped = head.parent(0).unwrap();
+    assert_eq!(skipped.message(), Some("wip 2"));
+    assert_eq!(skipped.parent(0).unwrap().message(), Some("add file\n"));
+}
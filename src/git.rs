@@ -1,8 +1,15 @@
 use anyhow::{Result, bail};
-use git2::{Delta, DiffFindOptions, DiffFormat, DiffOptions, Repository, RepositoryState};
-use std::path::Path;
+use git2::{
+    ApplyLocation, ApplyOptions, AttrCheckFlags, AttrValue, Delta, DiffFindOptions, DiffFormat,
+    DiffHunk, DiffOptions, Oid, Pathspec, PathspecFlags, Repository, RepositoryState, Sort,
+};
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 const RENAME_SIMILARITY_THRESHOLD: u16 = 50;
+const BACKUP_REF_PREFIX: &str = "refs/git-auto-commit/backup/";
 
 // trait extension to get clean error messages from git2 errors
 // (strips the ugly "class=X; code=Y" suffix)
@@ -16,19 +23,117 @@ impl Git2ErrorExt for git2::Error {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum FileType {
     Normal,
     Binary,
     Generated,
+    /// third-party code, marked `linguist-vendored` in .gitattributes
+    Vendored,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FileChange {
     pub status: Delta,
     pub path: String,
     pub old_path: Option<String>, // set for renames (Delta::Renamed)
     pub file_type: FileType,
+    /// hunks chosen by the user; None for the whole file
+    pub hunks: Option<Vec<HunkRange>>,
+    pub source: Source,
+}
+
+impl FileChange {
+    /// true if the user can choose individual hunks of this file
+    pub fn has_hunks(&self) -> bool {
+        self.status == Delta::Modified && self.file_type == FileType::Normal
+    }
+}
+
+/// where a file's changes are, relative to HEAD
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    Staged,
+    Unstaged,
+    /// staged, with further changes in the working directory
+    Both,
+    Untracked,
+}
+
+impl Source {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Staged => "staged",
+            Self::Unstaged => "unstaged",
+            Self::Both => "staged + unstaged",
+            Self::Untracked => "untracked",
+        }
+    }
+}
+
+/// which changes `get_changes` returns
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Scope {
+    /// staged changes if there are any, otherwise unstaged changes including
+    /// untracked files
+    #[default]
+    Auto,
+    /// staged and unstaged changes to tracked files, like `git commit -a`
+    Tracked,
+    /// staged and unstaged changes, including untracked files
+    All,
+}
+
+/// lines of a hunk in the new version of the file, which identify the hunk
+/// across diffs of the same changes
+///
+/// a diff with less context splits hunks rather than merging them, so a hunk
+/// matches a chosen hunk if it falls within the chosen hunk's lines.  the old
+/// version isn't compared, as its lines shift once earlier hunks are committed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HunkRange {
+    start: u32,
+    lines: u32,
+}
+
+impl HunkRange {
+    fn new(hunk: &DiffHunk) -> Self {
+        Self {
+            start: hunk.new_start(),
+            lines: hunk.new_lines(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn at(start: u32, lines: u32) -> Self {
+        Self { start, lines }
+    }
+
+    fn contains(self, hunk: &DiffHunk) -> bool {
+        let (start, lines) = (hunk.new_start(), hunk.new_lines());
+        if lines > 0 {
+            start >= self.start && start + lines <= self.start + self.lines
+        } else if self.lines > 0 {
+            // an empty range is positioned after its start line
+            start + 1 >= self.start && start < self.start + self.lines
+        } else {
+            start == self.start
+        }
+    }
+}
+
+/// a hunk of a file's diff, for the user to choose from
+pub struct Hunk {
+    pub range: HunkRange,
+    /// the hunk's header and lines, as in a unified diff
+    pub text: String,
+}
+
+/// the files and hunks the user chose to commit
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    /// chosen files by path, with their chosen hunks or None for the whole file
+    pub files: HashMap<String, Option<Vec<HunkRange>>>,
 }
 
 /// convert Delta to single-character status code for display
@@ -43,23 +148,53 @@ pub fn status_char(delta: Delta) -> char {
 }
 
 /// represents a set of changes (staged or unstaged)
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ChangeSet {
     pub files: Vec<FileChange>,
     pub diff: String,
     pub is_staged: bool,
+    /// true if the changes are HEAD's and any staged changes, to be amended
+    pub is_amend: bool,
+    /// the commit whose changes these are, when rewording it
+    pub commit: Option<Oid>,
+    /// root of the repository's working directory
+    pub repo: PathBuf,
+    /// files with changes the user chose not to commit
+    pub unselected: Vec<FileChange>,
+    /// files with changes outside the scope, which won't be committed, eg.
+    /// unstaged changes when there are staged ones
+    pub excluded: Vec<FileChange>,
+    pub scope: Scope,
+    /// pathspecs the changes are limited to, relative to the repository root;
+    /// empty for all paths
+    pub pathspecs: Vec<String>,
+    /// lines of context in the diff
+    pub context_lines: u32,
 }
 
 impl ChangeSet {
-    pub fn source(&self) -> &str {
-        if self.is_staged {
-            "staged changes"
+    pub fn source(&self) -> String {
+        if let Some(commit) = self.commit {
+            format!("commit {}", short_id(commit))
+        } else if self.is_amend {
+            String::from("HEAD and staged changes")
+        } else if self.is_staged {
+            String::from("staged changes")
         } else {
-            "unstaged changes"
+            String::from(match self.scope {
+                Scope::Auto => "unstaged changes",
+                Scope::Tracked => "staged and unstaged changes",
+                Scope::All => "all changes",
+            })
         }
     }
 }
 
+/// abbreviated commit id for display
+pub fn short_id(oid: Oid) -> String {
+    oid.to_string().chars().take(7).collect()
+}
+
 /// sanity check that we're in a git repository and in a good state
 pub fn sanity_check() -> Result<()> {
     // check we're in a git repository (can be anywhere within the repo)
@@ -79,48 +214,436 @@ pub fn sanity_check() -> Result<()> {
     Ok(())
 }
 
-/// get changes from the repository
-/// checks staged changes first, falls back to unstaged (including untracked files)
+/// get changes from the repository, as set by `scope`
+/// limited to paths matching the pathspecs, if any, and to the user's
+/// selection, if given; files matching the `generated` pathspecs are classed
+/// as generated
 /// returns None if no changes found
-pub fn get_changes(path: &Path, context_lines: u32) -> Result<Option<ChangeSet>> {
+pub fn get_changes(
+    path: &Path,
+    context_lines: u32,
+    scope: Scope,
+    pathspecs: &[String],
+    generated: &[String],
+    selection: Option<&Selection>,
+) -> Result<Option<ChangeSet>> {
     let repo = Repository::discover(path)
         .map_err(|e| anyhow::anyhow!("failed to open git repository: {}", e.clean()))?;
 
-    // try staged changes first
-    let staged_diff = create_staged_diff(&repo, context_lines)?;
-    if staged_diff
-        .stats()
-        .map_err(|e| anyhow::anyhow!("failed to get diff stats: {}", e.clean()))?
-        .files_changed()
-        > 0
-    {
-        let files = files_from_git_diff(&staged_diff);
-        let diff = format_diff(&staged_diff, &files)?;
-        return Ok(Some(ChangeSet {
-            files,
-            diff,
-            is_staged: true,
-        }));
+    let staged_diff = create_staged_diff(&repo, context_lines, pathspecs)?;
+    load_contents(&staged_diff)?;
+    let staged = files_from_git_diff(&repo, &staged_diff, Source::Staged, generated)?;
+    // unstaged changes include untracked files
+    let unstaged_diff = create_unstaged_diff(&repo, context_lines, pathspecs)?;
+    let unstaged = files_from_git_diff(&repo, &unstaged_diff, Source::Unstaged, generated)?;
+
+    let (diff, files, excluded, is_staged) = match scope {
+        Scope::Auto if !staged.is_empty() => (staged_diff, staged, unstaged, true),
+        Scope::Auto => (unstaged_diff, unstaged, Vec::new(), false),
+        Scope::Tracked | Scope::All => {
+            let diff = create_workdir_diff(&repo, context_lines, pathspecs, scope == Scope::All)?;
+            load_contents(&diff)?;
+            let files = with_sources(
+                files_from_git_diff(&repo, &diff, Source::Unstaged, generated)?,
+                &staged,
+                &unstaged,
+            );
+            let excluded = if scope == Scope::All {
+                Vec::new()
+            } else {
+                unstaged
+                    .into_iter()
+                    .filter(|f| f.source == Source::Untracked)
+                    .collect()
+            };
+            (diff, files, excluded, false)
+        }
+    };
+
+    if files.is_empty() {
+        return Ok(None);
+    }
+
+    let (files, unselected) = select_files(files, selection);
+    let diff_text = format_diff(&diff, &files)?;
+    Ok(Some(ChangeSet {
+        files,
+        diff: diff_text,
+        is_staged,
+        is_amend: false,
+        commit: None,
+        repo: workdir(&repo),
+        unselected,
+        excluded,
+        scope,
+        pathspecs: pathspecs.to_vec(),
+        context_lines,
+    }))
+}
+
+/// load the contents of the diff's files, so binary files are flagged
+fn load_contents(diff: &git2::Diff) -> Result<()> {
+    diff.stats()
+        .map_err(|e| anyhow::anyhow!("failed to get diff stats: {}", e.clean()))?;
+    Ok(())
+}
+
+/// set the source of files diffed from HEAD to the working directory, from
+/// whether they're among the staged and unstaged changes
+fn with_sources(
+    mut files: Vec<FileChange>,
+    staged: &[FileChange],
+    unstaged: &[FileChange],
+) -> Vec<FileChange> {
+    let paths = |files: &[FileChange]| -> HashSet<String> {
+        files
+            .iter()
+            .flat_map(|f| std::iter::once(f.path.clone()).chain(f.old_path.clone()))
+            .collect()
+    };
+    let (staged, unstaged) = (paths(staged), paths(unstaged));
+    for file in &mut files {
+        if file.source == Source::Untracked {
+            continue;
+        }
+        file.source = match (staged.contains(&file.path), unstaged.contains(&file.path)) {
+            (true, true) => Source::Both,
+            (true, false) => Source::Staged,
+            (false, _) => Source::Unstaged,
+        };
+    }
+    files
+}
+
+/// split files into those in the selection, with their chosen hunks, and
+/// those which aren't
+fn select_files(
+    files: Vec<FileChange>,
+    selection: Option<&Selection>,
+) -> (Vec<FileChange>, Vec<FileChange>) {
+    let Some(selection) = selection else {
+        return (files, Vec::new());
+    };
+    let mut selected = Vec::new();
+    let mut unselected = Vec::new();
+    for mut file in files {
+        if let Some(hunks) = selection.files.get(&file.path) {
+            file.hunks.clone_from(hunks);
+            selected.push(file);
+        } else {
+            unselected.push(file);
+        }
+    }
+    (selected, unselected)
+}
+
+/// the hunks of each file in the changeset, in order; files whose hunks
+/// can't be chosen individually have none
+pub fn hunks(path: &Path, changeset: &ChangeSet) -> Result<Vec<Vec<Hunk>>> {
+    let repo = Repository::discover(path)
+        .map_err(|e| anyhow::anyhow!("failed to open git repository: {}", e.clean()))?;
+    let diff = create_changeset_diff(&repo, changeset)?;
+
+    let mut by_path: HashMap<String, Vec<Hunk>> = HashMap::new();
+    diff.print(DiffFormat::Patch, |delta, hunk, line| {
+        let (Some(hunk), Some(path)) = (hunk, delta.new_file().path()) else {
+            return true;
+        };
+        let hunks = by_path
+            .entry(path.to_string_lossy().into_owned())
+            .or_default();
+        if line.origin() == 'H' {
+            hunks.push(Hunk {
+                range: HunkRange::new(&hunk),
+                text: String::new(),
+            });
+        }
+        if let Some(current) = hunks.last_mut() {
+            if matches!(line.origin(), '+' | '-' | ' ') {
+                current.text.push(line.origin());
+            }
+            current
+                .text
+                .push_str(std::str::from_utf8(line.content()).unwrap_or(""));
+        }
+        true
+    })
+    .map_err(|e| anyhow::anyhow!("failed to format diff: {}", e.clean()))?;
+
+    Ok(changeset
+        .files
+        .iter()
+        .map(|file| {
+            if file.has_hunks() {
+                by_path.remove(&file.path).unwrap_or_default()
+            } else {
+                Vec::new()
+            }
+        })
+        .collect())
+}
+
+/// get the changes amending HEAD would commit: HEAD's changes from its
+/// parent, and any staged changes
+/// returns None if there are none
+pub fn get_amend_changes(
+    path: &Path,
+    context_lines: u32,
+    generated: &[String],
+) -> Result<Option<ChangeSet>> {
+    let repo = Repository::discover(path)
+        .map_err(|e| anyhow::anyhow!("failed to open git repository: {}", e.clean()))?;
+
+    let diff = create_amend_diff(&repo, context_lines)?;
+    let files = files_from_git_diff(&repo, &diff, Source::Staged, generated)?;
+    if files.is_empty() {
+        return Ok(None);
     }
 
-    // no staged changes, try unstaged (includes untracked files)
-    let unstaged_diff = create_unstaged_diff(&repo, context_lines)?;
-    let files = files_from_git_diff(&unstaged_diff);
+    let diff_text = format_diff(&diff, &files)?;
+    Ok(Some(ChangeSet {
+        files,
+        diff: diff_text,
+        is_staged: true,
+        is_amend: true,
+        commit: None,
+        repo: workdir(&repo),
+        unselected: Vec::new(),
+        excluded: Vec::new(),
+        scope: Scope::Auto,
+        pathspecs: Vec::new(),
+        context_lines,
+    }))
+}
 
+/// get the changes made by a commit, to reword it
+/// returns None if the commit is empty
+pub fn get_commit_changes(
+    path: &Path,
+    commit: Oid,
+    context_lines: u32,
+    generated: &[String],
+) -> Result<Option<ChangeSet>> {
+    let repo = Repository::discover(path)
+        .map_err(|e| anyhow::anyhow!("failed to open git repository: {}", e.clean()))?;
+    let commit = repo
+        .find_commit(commit)
+        .map_err(|e| anyhow::anyhow!("failed to find commit: {}", e.clean()))?;
+
+    let diff = create_commit_diff(&repo, &commit, context_lines)?;
+    // a commit's changes are as if staged
+    let files = files_from_git_diff(&repo, &diff, Source::Staged, generated)?;
     if files.is_empty() {
         return Ok(None);
     }
 
-    let diff = format_diff(&unstaged_diff, &files)?;
+    let diff_text = format_diff(&diff, &files)?;
     Ok(Some(ChangeSet {
         files,
-        diff,
+        diff: diff_text,
         is_staged: false,
+        is_amend: false,
+        commit: Some(commit.id()),
+        repo: workdir(&repo),
+        unselected: Vec::new(),
+        excluded: Vec::new(),
+        scope: Scope::Auto,
+        pathspecs: Vec::new(),
+        context_lines,
     }))
 }
 
-/// extract list of files from a `git2::Diff` using native types
-fn files_from_git_diff(diff: &git2::Diff) -> Vec<FileChange> {
+/// the commits in `range`, oldest first, with their messages
+///
+/// the range is as for `git log`, eg. "main..HEAD"; a single revision means
+/// the commits after it.  the range must end at HEAD and not include merges,
+/// so it can be rewritten
+pub fn commits_in_range(path: &Path, range: &str) -> Result<Vec<(Oid, String)>> {
+    let repo = Repository::discover(path)
+        .map_err(|e| anyhow::anyhow!("failed to open git repository: {}", e.clean()))?;
+    let spec = if range.contains("..") {
+        range.to_string()
+    } else {
+        format!("{range}..HEAD")
+    };
+    let revspec = repo
+        .revparse(&spec)
+        .map_err(|e| anyhow::anyhow!("invalid range {range}: {}", e.clean()))?;
+    if revspec.mode().contains(git2::RevparseMode::MERGE_BASE) {
+        bail!("invalid range {range}: use two dots");
+    }
+    let (Some(from), Some(to)) = (revspec.from(), revspec.to()) else {
+        bail!("invalid range {range}");
+    };
+
+    let head = repo
+        .head()
+        .and_then(|head| head.peel_to_commit())
+        .map_err(|e| anyhow::anyhow!("failed to get HEAD commit: {}", e.clean()))?;
+    let to = to
+        .peel_to_commit()
+        .map_err(|e| anyhow::anyhow!("invalid range {range}: {}", e.clean()))?;
+    if to.id() != head.id() {
+        bail!("range {range} must end at HEAD");
+    }
+
+    let mut walk = repo
+        .revwalk()
+        .map_err(|e| anyhow::anyhow!("failed to walk commits: {}", e.clean()))?;
+    walk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)
+        .and_then(|()| walk.push(to.id()))
+        .and_then(|()| walk.hide(from.id()))
+        .map_err(|e| anyhow::anyhow!("failed to walk commits: {}", e.clean()))?;
+
+    let mut commits = Vec::new();
+    for oid in walk {
+        let commit = oid
+            .and_then(|oid| repo.find_commit(oid))
+            .map_err(|e| anyhow::anyhow!("failed to walk commits: {}", e.clean()))?;
+        if commit.parent_count() > 1 {
+            bail!("can't reword merge commit {}", short_id(commit.id()));
+        }
+        commits.push((commit.id(), commit.message().unwrap_or("").to_string()));
+    }
+    if commits.is_empty() {
+        bail!("no commits in range {range}");
+    }
+    Ok(commits)
+}
+
+/// rewrite the current branch with new messages for some of its commits,
+/// given oldest first with None to keep a message; `commits` must be the
+/// commits up to HEAD, as returned by `commits_in_range`
+///
+/// trees and authors are kept and the index and working directory are
+/// untouched.  rewritten commits lose any signature.  the branch is backed up
+/// first, and the backup's name is returned, or None if nothing changed
+pub fn reword(path: &Path, commits: &[(Oid, Option<String>)]) -> Result<Option<String>> {
+    let repo = Repository::discover(path)
+        .map_err(|e| anyhow::anyhow!("failed to open git repository: {}", e.clean()))?;
+    let head = repo
+        .head()
+        .map_err(|e| anyhow::anyhow!("failed to get HEAD: {}", e.clean()))?;
+    let (Some(branch), Some(name), Some(old_head)) = (
+        head.shorthand().map(String::from),
+        head.name().map(String::from),
+        head.target(),
+    ) else {
+        bail!("HEAD isn't a branch");
+    };
+    if commits.last().map(|(oid, _)| *oid) != Some(old_head) {
+        bail!("commits to reword must end at HEAD");
+    }
+    let committer = repo
+        .signature()
+        .map_err(|e| anyhow::anyhow!("failed to get committer: {}", e.clean()))?;
+
+    // leading commits with unchanged messages are kept as they are
+    let mut parent: Option<git2::Commit> = None;
+    let mut previous: Option<Oid> = None;
+    let mut rewritten = false;
+    for (oid, message) in commits {
+        let commit = repo
+            .find_commit(*oid)
+            .map_err(|e| anyhow::anyhow!("failed to find commit: {}", e.clean()))?;
+        if previous.is_some() && commit.parent_id(0).ok() != previous {
+            bail!("commits to reword must be consecutive");
+        }
+        previous = Some(*oid);
+        if !rewritten && message.is_none() {
+            parent = Some(commit);
+            continue;
+        }
+
+        let message = match message {
+            Some(message) => format!("{}\n", message.trim_end()),
+            None => commit.message().unwrap_or("").to_string(),
+        };
+        let parents: Vec<git2::Commit> = match parent {
+            Some(parent) => vec![parent],
+            None => commit.parents().collect(),
+        };
+        let parents: Vec<&git2::Commit> = parents.iter().collect();
+        let tree = commit
+            .tree()
+            .map_err(|e| anyhow::anyhow!("failed to get tree: {}", e.clean()))?;
+        let new = repo
+            .commit(
+                None,
+                &commit.author(),
+                &committer,
+                &message,
+                &tree,
+                &parents,
+            )
+            .and_then(|oid| repo.find_commit(oid))
+            .map_err(|e| anyhow::anyhow!("failed to rewrite commit: {}", e.clean()))?;
+        parent = Some(new);
+        rewritten = true;
+    }
+
+    let Some(new_head) = parent.filter(|_| rewritten) else {
+        return Ok(None);
+    };
+
+    // each backup is named for the commit it saves, as custom refs have no
+    // reflog to recover an overwritten one from
+    let backup = format!("{BACKUP_REF_PREFIX}{branch}-{}", short_id(old_head));
+    let existing = repo.find_reference(&backup).ok().and_then(|r| r.target());
+    if existing.is_some_and(|target| target != old_head) {
+        bail!("{backup} already exists");
+    }
+    if existing.is_none() {
+        repo.reference(
+            &backup,
+            old_head,
+            false,
+            "git-auto-commit: backup before reword",
+        )
+        .map_err(|e| anyhow::anyhow!("failed to create {backup}: {}", e.clean()))?;
+    }
+    // only move the branch if nothing else has since
+    repo.reference_matching(
+        &name,
+        new_head.id(),
+        true,
+        old_head,
+        "git-auto-commit: reword",
+    )
+    .map_err(|e| {
+        if e.code() == git2::ErrorCode::Modified {
+            anyhow::anyhow!("{branch} changed while rewording, so it wasn't updated")
+        } else {
+            anyhow::anyhow!("failed to update {branch}: {}", e.clean())
+        }
+    })?;
+    Ok(Some(backup))
+}
+
+/// root of the repository's working directory, or the git directory if bare
+fn workdir(repo: &Repository) -> PathBuf {
+    repo.workdir().unwrap_or_else(|| repo.path()).to_path_buf()
+}
+
+/// extract list of files from a `git2::Diff` using native types, with the
+/// given source unless they're untracked
+///
+/// files matching the `generated` pathspecs are classed as generated
+fn files_from_git_diff(
+    repo: &Repository,
+    diff: &git2::Diff,
+    source: Source,
+    generated: &[String],
+) -> Result<Vec<FileChange>> {
+    // an empty pathspec matches everything
+    let generated = if generated.is_empty() {
+        None
+    } else {
+        Some(
+            Pathspec::new(generated)
+                .map_err(|e| anyhow::anyhow!("invalid generated pattern: {}", e.clean()))?,
+        )
+    };
     let mut files = Vec::new();
 
     for delta in diff.deltas() {
@@ -146,97 +669,272 @@ fn files_from_git_diff(diff: &git2::Diff) -> Vec<FileChange> {
         };
 
         if let Some(path) = path {
-            let path_str = path.to_string_lossy().into_owned();
-
             let is_binary = delta.new_file().is_binary() || delta.old_file().is_binary();
 
-            let path_lower = path_str.to_lowercase();
-            #[allow(clippy::case_sensitive_file_extension_comparisons)]
-            let is_generated = path_lower.ends_with(".min.js")
-                || path_lower.ends_with(".min.css")
-                || path_lower.ends_with("-min.js")
-                || path_lower.ends_with("-min.css")
-                || path_lower.ends_with(".lock")
-                || path_lower.ends_with("-lock.json")
-                || path_lower.ends_with("-lock.yaml");
-
             files.push(FileChange {
                 status,
-                path: path_str,
+                path: path.to_string_lossy().into_owned(),
                 old_path,
-                file_type: if is_binary {
-                    FileType::Binary
-                } else if is_generated {
-                    FileType::Generated
+                file_type: file_type(repo, path, is_binary, generated.as_ref()),
+                hunks: None,
+                source: if status == Delta::Untracked {
+                    Source::Untracked
                 } else {
-                    FileType::Normal
+                    source
                 },
             });
         }
     }
 
-    files
+    Ok(files)
+}
+
+/// classify a file by its contents, its gitattributes, the `generated`
+/// pathspecs, and its name; gitattributes take precedence
+fn file_type(
+    repo: &Repository,
+    path: &Path,
+    is_binary: bool,
+    generated: Option<&Pathspec>,
+) -> FileType {
+    // "binary" and "-diff" both unset the diff attribute
+    if is_binary || attr(repo, path, "diff") == Some(false) {
+        return FileType::Binary;
+    }
+    if attr(repo, path, "linguist-vendored") == Some(true) {
+        return FileType::Vendored;
+    }
+    if let Some(is_generated) = attr(repo, path, "linguist-generated") {
+        return if is_generated {
+            FileType::Generated
+        } else {
+            FileType::Normal
+        };
+    }
+
+    let path_lower = path.to_string_lossy().to_lowercase();
+    #[allow(clippy::case_sensitive_file_extension_comparisons)]
+    let is_generated = path_lower.ends_with(".min.js")
+        || path_lower.ends_with(".min.css")
+        || path_lower.ends_with("-min.js")
+        || path_lower.ends_with("-min.css")
+        || path_lower.ends_with(".lock")
+        || path_lower.ends_with("-lock.json")
+        || path_lower.ends_with("-lock.yaml")
+        || generated.is_some_and(|generated| generated.matches_path(path, PathspecFlags::DEFAULT));
+
+    if is_generated {
+        FileType::Generated
+    } else {
+        FileType::Normal
+    }
+}
+
+/// a boolean gitattribute of the file, or None if it isn't specified
+fn attr(repo: &Repository, path: &Path, name: &str) -> Option<bool> {
+    let value = repo
+        .get_attr(path, name, AttrCheckFlags::FILE_THEN_INDEX)
+        .ok()?;
+    match AttrValue::from_string(value) {
+        AttrValue::True => Some(true),
+        AttrValue::False => Some(false),
+        // eg. "linguist-generated=false"; other values, such as a diff
+        // driver, count as set
+        AttrValue::String(value) => Some(value != "false"),
+        AttrValue::Bytes(_) | AttrValue::Unspecified => None,
+    }
+}
+
+/// HEAD's tree, or None on an unborn branch (no commits yet)
+fn head_tree(repo: &Repository) -> Result<Option<git2::Tree<'_>>> {
+    match repo.head() {
+        Ok(head) => {
+            Ok(Some(head.peel_to_tree().map_err(|e| {
+                anyhow::anyhow!("failed to get tree: {}", e.clean())
+            })?))
+        }
+        Err(e) if e.code() == git2::ErrorCode::UnbornBranch => Ok(None),
+        Err(e) => bail!("failed to get HEAD: {}", e.clean()),
+    }
+}
+
+/// HEAD's commit, or None on an unborn branch (no commits yet)
+fn head_commit(repo: &Repository) -> Result<Option<git2::Commit<'_>>> {
+    match repo.head() {
+        Ok(head) => Ok(Some(head.peel_to_commit().map_err(|e| {
+            anyhow::anyhow!("failed to get HEAD commit: {}", e.clean())
+        })?)),
+        Err(e) if e.code() == git2::ErrorCode::UnbornBranch => Ok(None),
+        Err(e) => bail!("failed to get HEAD: {}", e.clean()),
+    }
+}
+
+/// create a diff object for the changes in the changeset, without selection
+fn create_changeset_diff<'a>(
+    repo: &'a Repository,
+    changeset: &ChangeSet,
+) -> Result<git2::Diff<'a>> {
+    if changeset.is_amend {
+        create_amend_diff(repo, changeset.context_lines)
+    } else if changeset.is_staged {
+        create_staged_diff(repo, changeset.context_lines, &changeset.pathspecs)
+    } else {
+        let (context_lines, pathspecs) = (changeset.context_lines, &changeset.pathspecs);
+        match changeset.scope {
+            Scope::Auto => create_unstaged_diff(repo, context_lines, pathspecs),
+            Scope::Tracked => create_workdir_diff(repo, context_lines, pathspecs, false),
+            Scope::All => create_workdir_diff(repo, context_lines, pathspecs, true),
+        }
+    }
 }
 
 /// create a diff object for staged changes
-fn create_staged_diff(repo: &Repository, context_lines: u32) -> Result<git2::Diff<'_>> {
-    // handle unborn branch (no commits yet) - compare against empty tree
-    let tree = match repo.head() {
-        Ok(head) => Some(
-            head.peel_to_tree()
+fn create_staged_diff<'a>(
+    repo: &'a Repository,
+    context_lines: u32,
+    pathspecs: &[String],
+) -> Result<git2::Diff<'a>> {
+    // compare against the empty tree on an unborn branch
+    let tree = head_tree(repo)?;
+    create_index_diff(repo, tree.as_ref(), context_lines, pathspecs)
+}
+
+/// create a diff object for the changes of HEAD and any staged changes
+fn create_amend_diff(repo: &Repository, context_lines: u32) -> Result<git2::Diff<'_>> {
+    let head = match repo.head() {
+        Ok(head) => head
+            .peel_to_commit()
+            .map_err(|e| anyhow::anyhow!("failed to get HEAD commit: {}", e.clean()))?,
+        Err(e) if e.code() == git2::ErrorCode::UnbornBranch => bail!("no commit to amend"),
+        Err(e) => bail!("failed to get HEAD: {}", e.clean()),
+    };
+
+    // the root commit is compared against the empty tree
+    let tree = match head.parent(0) {
+        Ok(parent) => Some(
+            parent
+                .tree()
                 .map_err(|e| anyhow::anyhow!("failed to get tree: {}", e.clean()))?,
         ),
-        Err(e) if e.code() == git2::ErrorCode::UnbornBranch => None,
-        Err(e) => bail!("failed to get HEAD: {}", e.clean()),
+        Err(e) if e.code() == git2::ErrorCode::NotFound => None,
+        Err(e) => bail!("failed to get parent of HEAD: {}", e.clean()),
     };
 
-    let mut opts = DiffOptions::new();
-    opts.context_lines(context_lines);
+    create_index_diff(repo, tree.as_ref(), context_lines, &[])
+}
+
+/// create a diff object from a tree to the index
+fn create_index_diff<'a>(
+    repo: &'a Repository,
+    tree: Option<&git2::Tree>,
+    context_lines: u32,
+    pathspecs: &[String],
+) -> Result<git2::Diff<'a>> {
+    let mut opts = diff_options(context_lines, pathspecs);
 
     let mut diff = repo
-        .diff_tree_to_index(tree.as_ref(), None, Some(&mut opts))
+        .diff_tree_to_index(tree, None, Some(&mut opts))
         .map_err(|e| anyhow::anyhow!("failed to create diff: {}", e.clean()))?;
 
-    // enable rename detection with lower threshold for better detection
-    let mut find_opts = DiffFindOptions::new();
-    find_opts.renames(true);
-    find_opts.rename_threshold(RENAME_SIMILARITY_THRESHOLD);
-    find_opts.copy_threshold(RENAME_SIMILARITY_THRESHOLD);
-    diff.find_similar(Some(&mut find_opts))
-        .map_err(|e| anyhow::anyhow!("failed to detect renames: {}", e.clean()))?;
-
+    detect_renames(&mut diff)?;
     Ok(diff)
 }
 
 /// create a diff object for unstaged changes
-fn create_unstaged_diff(repo: &Repository, context_lines: u32) -> Result<git2::Diff<'_>> {
-    let mut opts = DiffOptions::new();
+fn create_unstaged_diff<'a>(
+    repo: &'a Repository,
+    context_lines: u32,
+    pathspecs: &[String],
+) -> Result<git2::Diff<'a>> {
+    let mut opts = diff_options(context_lines, pathspecs);
     opts.include_untracked(true);
     opts.recurse_untracked_dirs(true);
     opts.show_untracked_content(true);
-    opts.context_lines(context_lines);
     let mut diff = repo
         .diff_index_to_workdir(None, Some(&mut opts))
         .map_err(|e| anyhow::anyhow!("failed to create diff: {}", e.clean()))?;
 
-    // enable rename detection with lower threshold for better detection
+    detect_renames(&mut diff)?;
+    Ok(diff)
+}
+
+/// create a diff object for staged and unstaged changes together, from HEAD
+/// to the working directory
+fn create_workdir_diff<'a>(
+    repo: &'a Repository,
+    context_lines: u32,
+    pathspecs: &[String],
+    include_untracked: bool,
+) -> Result<git2::Diff<'a>> {
+    let tree = head_tree(repo)?;
+    let mut opts = diff_options(context_lines, pathspecs);
+    opts.include_untracked(include_untracked);
+    opts.recurse_untracked_dirs(include_untracked);
+    opts.show_untracked_content(include_untracked);
+    let mut diff = repo
+        .diff_tree_to_workdir_with_index(tree.as_ref(), Some(&mut opts))
+        .map_err(|e| anyhow::anyhow!("failed to create diff: {}", e.clean()))?;
+
+    detect_renames(&mut diff)?;
+    Ok(diff)
+}
+
+/// create a diff object for the changes a commit made to its parent
+fn create_commit_diff<'a>(
+    repo: &'a Repository,
+    commit: &git2::Commit,
+    context_lines: u32,
+) -> Result<git2::Diff<'a>> {
+    let tree = commit
+        .tree()
+        .map_err(|e| anyhow::anyhow!("failed to get tree: {}", e.clean()))?;
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(
+            parent
+                .tree()
+                .map_err(|e| anyhow::anyhow!("failed to get tree: {}", e.clean()))?,
+        ),
+        Err(e) if e.code() == git2::ErrorCode::NotFound => None,
+        Err(e) => bail!("failed to get parent commit: {}", e.clean()),
+    };
+
+    let mut opts = diff_options(context_lines, &[]);
+    let mut diff = repo
+        .diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), Some(&mut opts))
+        .map_err(|e| anyhow::anyhow!("failed to create diff: {}", e.clean()))?;
+    detect_renames(&mut diff)?;
+    Ok(diff)
+}
+
+/// options for a diff with the given context, limited to the pathspecs if any
+fn diff_options(context_lines: u32, pathspecs: &[String]) -> DiffOptions {
+    let mut opts = DiffOptions::new();
+    opts.context_lines(context_lines);
+    for pathspec in pathspecs {
+        opts.pathspec(pathspec);
+    }
+    opts
+}
+
+/// enable rename detection with lower threshold for better detection
+fn detect_renames(diff: &mut git2::Diff) -> Result<()> {
     let mut find_opts = DiffFindOptions::new();
     find_opts.renames(true);
     find_opts.rename_threshold(RENAME_SIMILARITY_THRESHOLD);
     find_opts.copy_threshold(RENAME_SIMILARITY_THRESHOLD);
     diff.find_similar(Some(&mut find_opts))
-        .map_err(|e| anyhow::anyhow!("failed to detect renames: {}", e.clean()))?;
-
-    Ok(diff)
+        .map_err(|e| anyhow::anyhow!("failed to detect renames: {}", e.clean()))
 }
 
-/// format a diff object into unified diff string, skipping ignored files
+/// format a diff object into unified diff string, skipping ignored files,
+/// files not in the list, and hunks which weren't chosen
 fn format_diff(diff: &git2::Diff, files: &[FileChange]) -> Result<String> {
     let mut output = String::new();
-    let mut current_file: Option<String> = None;
+    let mut current_file: Option<&FileChange> = None;
     let mut skip_current_file = false;
+    let mut skip_current_hunk = false;
 
-    diff.print(DiffFormat::Patch, |delta, _hunk, line| {
+    diff.print(DiffFormat::Patch, |delta, hunk, line| {
         let origin = line.origin();
 
         // check for file header to determine if we should skip this file
@@ -244,13 +942,10 @@ fn format_diff(diff: &git2::Diff, files: &[FileChange]) -> Result<String> {
             && let Some(path) = delta.new_file().path()
         {
             let path_str = path.to_string_lossy().into_owned();
-            current_file = Some(path_str.clone());
+            current_file = files.iter().find(|f| f.path == path_str);
 
             // check if this file should be ignored based on files list
-            skip_current_file = files
-                .iter()
-                .find(|f| f.path == path_str)
-                .is_some_and(|f| f.file_type != FileType::Normal);
+            skip_current_file = current_file.is_some_and(|f| f.file_type != FileType::Normal);
 
             if skip_current_file {
                 // add a note that this file's diff was ignored
@@ -258,10 +953,20 @@ fn format_diff(diff: &git2::Diff, files: &[FileChange]) -> Result<String> {
                 let _ = writeln!(output, "--- {path_str} (diff ignored)");
                 return true;
             }
+            skip_current_file = current_file.is_none();
         }
 
-        // skip content if current file is ignored
-        if skip_current_file {
+        // check for hunk header to determine if we should skip this hunk
+        if origin == 'H'
+            && let Some(hunk) = &hunk
+        {
+            skip_current_hunk = current_file
+                .and_then(|f| f.hunks.as_ref())
+                .is_some_and(|ranges| !ranges.iter().any(|range| range.contains(hunk)));
+        }
+
+        // skip content if current file or hunk is ignored
+        if skip_current_file || (hunk.is_some() && skip_current_hunk) {
             return true;
         }
 
@@ -283,10 +988,38 @@ fn format_diff(diff: &git2::Diff, files: &[FileChange]) -> Result<String> {
     Ok(output.trim_end_matches('\n').to_string())
 }
 
-/// stage all files in the changeset
+/// stage the files and hunks in the changeset
+///
+/// for staged changes, this unstages the files and hunks the user chose not
+/// to commit; they remain in the working directory
 pub fn stage(path: &Path, changeset: &ChangeSet) -> Result<()> {
     let repo = Repository::discover(path)
         .map_err(|e| anyhow::anyhow!("failed to open git repository: {}", e.clean()))?;
+    if changeset.is_staged {
+        return unstage_unselected(&repo, changeset);
+    }
+
+    // changes are diffed from HEAD when they may already be staged, so files
+    // the user didn't choose are unstaged, and partially chosen files start
+    // again from HEAD
+    if changeset.scope != Scope::Auto {
+        let files: Vec<&FileChange> = changeset
+            .unselected
+            .iter()
+            .chain(changeset.files.iter().filter(|f| f.hunks.is_some()))
+            .collect();
+        let head = head_commit(&repo)?;
+        unstage(&repo, head.as_ref(), &files)?;
+    }
+
+    // stage chosen hunks first, as applying them writes the index
+    let mut opts = ApplyOptions::new();
+    if apply_chosen_hunks(&mut opts, &changeset.files) {
+        let diff = create_changeset_diff(&repo, changeset)?;
+        repo.apply(&diff, ApplyLocation::Index, Some(&mut opts))
+            .map_err(|e| anyhow::anyhow!("failed to stage chosen hunks: {}", e.clean()))?;
+    }
+
     let mut index = repo
         .index()
         .map_err(|e| anyhow::anyhow!("failed to get git index: {}", e.clean()))?;
@@ -294,8 +1027,8 @@ pub fn stage(path: &Path, changeset: &ChangeSet) -> Result<()> {
     // collect all errors before writing index
     let mut errors = Vec::new();
 
-    // stage each file according to its status
-    for file in &changeset.files {
+    // stage each whole file according to its status
+    for file in changeset.files.iter().filter(|f| f.hunks.is_none()) {
         let path = &file.path;
         match file.status {
             Delta::Deleted => {
@@ -352,13 +1085,122 @@ pub fn stage(path: &Path, changeset: &ChangeSet) -> Result<()> {
     Ok(())
 }
 
-/// create a commit with the given message
+/// reset the files the user chose not to commit to HEAD in the index, and
+/// restage only the chosen hunks of partially chosen files
+fn unstage_unselected(repo: &Repository, changeset: &ChangeSet) -> Result<()> {
+    let partial: Vec<&FileChange> = changeset
+        .files
+        .iter()
+        .filter(|f| f.hunks.is_some())
+        .collect();
+    if changeset.unselected.is_empty() && partial.is_empty() {
+        return Ok(());
+    }
+
+    // apply the chosen hunks to HEAD, before the index is reset
+    let head = head_commit(repo)?;
+    let mut entries = Vec::new();
+    let mut opts = ApplyOptions::new();
+    if let Some(head) = &head
+        && apply_chosen_hunks(&mut opts, &changeset.files)
+    {
+        let diff = create_staged_diff(repo, changeset.context_lines, &changeset.pathspecs)?;
+        let tree = head
+            .tree()
+            .map_err(|e| anyhow::anyhow!("failed to get tree: {}", e.clean()))?;
+        let applied = repo
+            .apply_to_tree(&tree, &diff, Some(&mut opts))
+            .map_err(|e| anyhow::anyhow!("failed to apply chosen hunks: {}", e.clean()))?;
+        for file in &partial {
+            if let Some(entry) = applied.get_path(Path::new(&file.path), 0) {
+                entries.push(entry);
+            }
+        }
+    }
+
+    let files: Vec<&FileChange> = changeset.unselected.iter().chain(partial).collect();
+    unstage(repo, head.as_ref(), &files)?;
+
+    let mut index = repo
+        .index()
+        .map_err(|e| anyhow::anyhow!("failed to get git index: {}", e.clean()))?;
+    for entry in &entries {
+        index
+            .add(entry)
+            .map_err(|e| anyhow::anyhow!("failed to stage chosen hunks: {}", e.clean()))?;
+    }
+    index
+        .write()
+        .map_err(|e| anyhow::anyhow!("failed to write git index: {}", e.clean()))
+}
+
+/// reset the files to `head` in the index, leaving the working directory
+fn unstage(repo: &Repository, head: Option<&git2::Commit>, files: &[&FileChange]) -> Result<()> {
+    if files.is_empty() {
+        return Ok(());
+    }
+    let paths: Vec<&str> = files
+        .iter()
+        .flat_map(|f| std::iter::once(f.path.as_str()).chain(f.old_path.as_deref()))
+        .collect();
+    repo.reset_default(head.map(git2::Commit::as_object), paths)
+        .map_err(|e| anyhow::anyhow!("failed to unstage files: {}", e.clean()))
+}
+
+/// set options to apply only the chosen hunks of partially chosen files;
+/// false if every file was chosen whole
+///
+/// the options must not be moved once set, as git2 points libgit2 at them
+fn apply_chosen_hunks<'a>(opts: &mut ApplyOptions<'a>, files: &'a [FileChange]) -> bool {
+    let partial: HashMap<&str, &Vec<HunkRange>> = files
+        .iter()
+        .filter_map(|f| f.hunks.as_ref().map(|hunks| (f.path.as_str(), hunks)))
+        .collect();
+    if partial.is_empty() {
+        return false;
+    }
+
+    // the hunk callback isn't told which file the hunk belongs to
+    let current = Rc::new(Cell::new(None));
+    let delta_current = Rc::clone(&current);
+    opts.delta_callback(move |delta| {
+        let ranges = delta
+            .and_then(|delta| delta.new_file().path())
+            .and_then(|path| partial.get(path.to_str()?).copied());
+        delta_current.set(ranges);
+        ranges.is_some()
+    });
+    opts.hunk_callback(move |hunk| {
+        hunk.zip(current.get())
+            .is_some_and(|(hunk, ranges)| ranges.iter().any(|range| range.contains(&hunk)))
+    });
+    true
+}
+
+/// create a commit of the staged changeset with the given message, or amend
+/// HEAD
 ///
 /// uses the git binary rather than git2 to ensure commit signing (gpg/ssh)
 /// and git hooks (pre-commit, commit-msg, etc.) work as expected
-pub fn commit(path: &Path, commit_description: &str) -> Result<()> {
-    let status = std::process::Command::new("git")
-        .arg("commit")
+pub fn commit(path: &Path, commit_description: &str, changeset: &ChangeSet) -> Result<()> {
+    let mut command = std::process::Command::new("git");
+    command.arg("commit");
+    if changeset.is_amend {
+        command.arg("--amend");
+    }
+
+    // limited to pathspecs, only the changeset's files are committed, through
+    // a temporary index; anything else staged stays staged
+    let index = if changeset.pathspecs.is_empty() {
+        None
+    } else {
+        Some(partial_index(path, changeset)?)
+    };
+    if let Some(index) = &index {
+        command.env("GIT_INDEX_FILE", index);
+    }
+
+    let status = command
         .arg("--message")
         .arg(commit_description)
         .current_dir(path)
@@ -372,5 +1214,99 @@ pub fn commit(path: &Path, commit_description: &str) -> Result<()> {
     Ok(())
 }
 
+/// write a temporary index of HEAD with the changeset's files as staged
+fn partial_index(path: &Path, changeset: &ChangeSet) -> Result<tempfile::TempPath> {
+    let repo = Repository::discover(path)
+        .map_err(|e| anyhow::anyhow!("failed to open git repository: {}", e.clean()))?;
+    let staged = repo
+        .index()
+        .map_err(|e| anyhow::anyhow!("failed to get git index: {}", e.clean()))?;
+
+    let file = tempfile::Builder::new()
+        .prefix("git-auto-commit-index")
+        .tempfile_in(repo.path())
+        .map_err(|e| anyhow::anyhow!("failed to create temporary index: {e}"))?
+        .into_temp_path();
+    // libgit2 creates the index, but can't read an empty file
+    std::fs::remove_file(&file)
+        .map_err(|e| anyhow::anyhow!("failed to create temporary index: {e}"))?;
+    let mut index = git2::Index::open(&file)
+        .map_err(|e| anyhow::anyhow!("failed to open temporary index: {}", e.clean()))?;
+    if let Some(tree) = head_tree(&repo)? {
+        index
+            .read_tree(&tree)
+            .map_err(|e| anyhow::anyhow!("failed to read HEAD into index: {}", e.clean()))?;
+    }
+
+    let paths = changeset
+        .files
+        .iter()
+        .flat_map(|f| std::iter::once(f.path.as_str()).chain(f.old_path.as_deref()));
+    for path in paths {
+        let result = match staged.get_path(Path::new(path), 0) {
+            Some(entry) => index.add(&entry),
+            None => index.remove_path(Path::new(path)),
+        };
+        result.map_err(|e| anyhow::anyhow!("failed to stage {path}: {}", e.clean()))?;
+    }
+    index
+        .write()
+        .map_err(|e| anyhow::anyhow!("failed to write temporary index: {}", e.clean()))?;
+    Ok(file)
+}
+
+/// pathspecs given relative to `path`, made relative to the repository root
+/// as git2 expects
+pub fn resolve_pathspecs(path: &Path, pathspecs: &[String]) -> Result<Vec<String>> {
+    use std::path::Component;
+
+    let repo = Repository::discover(path)
+        .map_err(|e| anyhow::anyhow!("failed to open git repository: {}", e.clean()))?;
+    let Some(root) = repo.workdir() else {
+        bail!("pathspecs need a working directory");
+    };
+    let canonical = |path: &Path| {
+        path.canonicalize()
+            .map_err(|e| anyhow::anyhow!("failed to resolve {}: {e}", path.display()))
+    };
+    let (root, cwd) = (canonical(root)?, canonical(path)?);
+    let prefix = cwd.strip_prefix(&root).unwrap_or(Path::new(""));
+
+    let mut resolved = Vec::new();
+    for pathspec in pathspecs {
+        let given = Path::new(pathspec);
+        let (mut components, relative): (Vec<String>, &Path) = if given.is_absolute() {
+            let relative = given
+                .strip_prefix(&root)
+                .map_err(|_| anyhow::anyhow!("{pathspec} is outside the repository"))?;
+            (Vec::new(), relative)
+        } else {
+            let prefix = prefix
+                .iter()
+                .map(|c| c.to_string_lossy().into_owned())
+                .collect();
+            (prefix, given)
+        };
+        for component in relative.components() {
+            match component {
+                Component::Normal(name) => components.push(name.to_string_lossy().into_owned()),
+                Component::ParentDir => {
+                    components
+                        .pop()
+                        .ok_or_else(|| anyhow::anyhow!("{pathspec} is outside the repository"))?;
+                }
+                _ => {}
+            }
+        }
+        // the root matches everything
+        if components.is_empty() {
+            resolved.push(String::from("*"));
+        } else {
+            resolved.push(components.join("/"));
+        }
+    }
+    Ok(resolved)
+}
+
 #[cfg(test)]
 mod tests;
@@ -76,7 +76,15 @@ fn test_file_rename() {
     index.write().unwrap();
 
     // get changes - should detect rename
-    let changes = get_changes(repo_path, crate::constants::DEFAULT_CONTEXT).unwrap();
+    let changes = get_changes(
+        repo_path,
+        crate::constants::DEFAULT_CONTEXT,
+        Scope::Auto,
+        &[],
+        &[],
+        None,
+    )
+    .unwrap();
 
     assert!(changes.is_some());
     let changeset = changes.unwrap();
@@ -124,7 +132,15 @@ fn test_file_move_to_subdirectory() {
     index.write().unwrap();
 
     // get changes
-    let changes = get_changes(repo_path, crate::constants::DEFAULT_CONTEXT).unwrap();
+    let changes = get_changes(
+        repo_path,
+        crate::constants::DEFAULT_CONTEXT,
+        Scope::Auto,
+        &[],
+        &[],
+        None,
+    )
+    .unwrap();
 
     assert!(changes.is_some());
     let changeset = changes.unwrap();
@@ -179,7 +195,15 @@ fn test_mixed_operations() {
     index.write().unwrap();
 
     // get changes
-    let changes = get_changes(repo_path, crate::constants::DEFAULT_CONTEXT).unwrap();
+    let changes = get_changes(
+        repo_path,
+        crate::constants::DEFAULT_CONTEXT,
+        Scope::Auto,
+        &[],
+        &[],
+        None,
+    )
+    .unwrap();
 
     assert!(changes.is_some());
     let changeset = changes.unwrap();
@@ -223,7 +247,15 @@ fn test_binary_file_is_ignored() {
     index.write().unwrap();
 
     // get changes
-    let changes = get_changes(repo_path, crate::constants::DEFAULT_CONTEXT).unwrap();
+    let changes = get_changes(
+        repo_path,
+        crate::constants::DEFAULT_CONTEXT,
+        Scope::Auto,
+        &[],
+        &[],
+        None,
+    )
+    .unwrap();
 
     assert!(changes.is_some());
     let changeset = changes.unwrap();
@@ -278,7 +310,15 @@ fn test_lock_file_is_ignored() {
     index.write().unwrap();
 
     // get changes
-    let changes = get_changes(repo_path, crate::constants::DEFAULT_CONTEXT).unwrap();
+    let changes = get_changes(
+        repo_path,
+        crate::constants::DEFAULT_CONTEXT,
+        Scope::Auto,
+        &[],
+        &[],
+        None,
+    )
+    .unwrap();
 
     assert!(changes.is_some());
     let changeset = changes.unwrap();
@@ -308,6 +348,66 @@ fn test_lock_file_is_ignored() {
     );
 }
 
+#[test]
+fn test_gitattributes_and_generated_patterns() {
+    let (temp_dir, repo) = setup_test_repo();
+    let repo_path = temp_dir.path();
+    create_file(
+        &repo_path.join(".gitattributes"),
+        "*.pb.go linguist-generated\n\
+         vendor/** linguist-vendored\n\
+         *.snap -diff\n\
+         *.dat binary\n\
+         package-lock.json -linguist-generated\n",
+    );
+    commit_all(&repo, "initial commit");
+
+    fs::create_dir_all(repo_path.join("vendor/lib")).unwrap();
+    fs::create_dir(repo_path.join("proto")).unwrap();
+    for name in [
+        "api.pb.go",
+        "vendor/lib/lib.go",
+        "test.snap",
+        "data.dat",
+        "package-lock.json",
+        "proto/out.rs",
+        "main.rs",
+    ] {
+        create_file(&repo_path.join(name), "content\n");
+    }
+
+    let changeset = get_changes(
+        repo_path,
+        crate::constants::DEFAULT_CONTEXT,
+        Scope::Auto,
+        &[],
+        &[String::from("proto/")],
+        None,
+    )
+    .unwrap()
+    .unwrap();
+    let file_type = |path: &str| {
+        changeset
+            .files
+            .iter()
+            .find(|f| f.path == path)
+            .map(|f| f.file_type.clone())
+            .unwrap()
+    };
+    assert_eq!(file_type("api.pb.go"), FileType::Generated);
+    assert_eq!(file_type("vendor/lib/lib.go"), FileType::Vendored);
+    assert_eq!(file_type("test.snap"), FileType::Binary);
+    assert_eq!(file_type("data.dat"), FileType::Binary);
+    // attributes override the built-in names
+    assert_eq!(file_type("package-lock.json"), FileType::Normal);
+    assert_eq!(file_type("proto/out.rs"), FileType::Generated);
+    assert_eq!(file_type("main.rs"), FileType::Normal);
+
+    assert!(changeset.diff.contains("vendor/lib/lib.go (diff ignored)"));
+    assert!(changeset.diff.contains("+++ b/main.rs"));
+    assert!(!changeset.diff.contains("+++ b/proto/out.rs"));
+}
+
 #[test]
 fn test_stage_function_with_deletions_and_renames() {
     let (temp_dir, repo) = setup_test_repo();
@@ -330,34 +430,25 @@ fn test_stage_function_with_deletions_and_renames() {
     create_file(&repo_path.join("new_file.txt"), "new"); // add
 
     // get unstaged changes
-    let changes = get_changes(repo_path, crate::constants::DEFAULT_CONTEXT).unwrap();
+    let changes = get_changes(
+        repo_path,
+        crate::constants::DEFAULT_CONTEXT,
+        Scope::Auto,
+        &[],
+        &[],
+        None,
+    )
+    .unwrap();
     assert!(changes.is_some());
     let changeset = changes.unwrap();
     assert!(!changeset.is_staged, "changes should be unstaged");
 
-    println!(
-        "Before staging - {} unstaged file(s):",
-        changeset.files.len()
-    );
-    for file in &changeset.files {
-        println!("  status: {:?}, path: {}", file.status, file.path);
-    }
-
     // stage the changes using our stage() function
     stage(repo_path, &changeset).expect("staging should succeed");
 
     // verify all changes are now staged
-    let staged_diff = create_staged_diff(&repo, crate::constants::DEFAULT_CONTEXT).unwrap();
-    let staged_files = files_from_git_diff(&staged_diff);
-
-    println!("After staging - {} staged file(s):", staged_files.len());
-    for file in &staged_files {
-        if let Some(old_path) = &file.old_path {
-            println!("  status: {:?}, {} → {}", file.status, old_path, file.path);
-        } else {
-            println!("  status: {:?}, path: {}", file.status, file.path);
-        }
-    }
+    let staged_diff = create_staged_diff(&repo, crate::constants::DEFAULT_CONTEXT, &[]).unwrap();
+    let staged_files = files_from_git_diff(&repo, &staged_diff, Source::Staged, &[]).unwrap();
 
     // verify we have the expected changes staged
     assert_eq!(
@@ -387,3 +478,412 @@ fn test_stage_function_with_deletions_and_renames() {
     assert!(has_renamed, "renamed file should be staged as rename");
     assert!(has_added, "new file should be staged");
 }
+
+/// helper to create a committed file of numbered lines, then change its
+/// second and second last lines, which are far enough apart to be separate
+/// hunks, and add an untracked file
+fn setup_two_hunks(repo: &Repository, repo_path: &Path) -> Selection {
+    let lines: Vec<String> = (1..=20).map(|n| format!("line {n}\n")).collect();
+    create_file(&repo_path.join("a.txt"), &lines.concat());
+    commit_all(repo, "initial commit");
+
+    let mut changed = lines.clone();
+    changed[1] = String::from("changed 2\n");
+    changed[18] = String::from("changed 19\n");
+    create_file(&repo_path.join("a.txt"), &changed.concat());
+    create_file(&repo_path.join("b.txt"), "new\n");
+
+    let changeset = get_changes(
+        repo_path,
+        crate::constants::DEFAULT_CONTEXT,
+        Scope::Auto,
+        &[],
+        &[],
+        None,
+    )
+    .unwrap()
+    .unwrap();
+    let hunks = hunks(repo_path, &changeset).unwrap();
+    let a = changeset
+        .files
+        .iter()
+        .position(|f| f.path == "a.txt")
+        .unwrap();
+    assert_eq!(hunks[a].len(), 2);
+    assert!(hunks[a][0].text.contains("+changed 2\n"));
+
+    // choose the first hunk of a.txt only
+    let mut selection = Selection::default();
+    selection
+        .files
+        .insert(String::from("a.txt"), Some(vec![hunks[a][0].range]));
+    selection
+}
+
+/// content of a file in the index
+fn indexed(repo: &Repository, path: &str) -> Option<String> {
+    let mut index = repo.index().unwrap();
+    index.read(true).unwrap();
+    let entry = index.get_path(Path::new(path), 0)?;
+    let blob = repo.find_blob(entry.id).unwrap();
+    Some(String::from_utf8(blob.content().to_vec()).unwrap())
+}
+
+#[test]
+fn test_selected_hunks_unstaged() {
+    let (temp_dir, repo) = setup_test_repo();
+    let repo_path = temp_dir.path();
+    let selection = setup_two_hunks(&repo, repo_path);
+
+    // the diff only contains the chosen hunk, even with less context
+    let changeset = get_changes(
+        repo_path,
+        crate::constants::LESS_CONTEXT,
+        Scope::Auto,
+        &[],
+        &[],
+        Some(&selection),
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(changeset.files.len(), 1);
+    assert_eq!(changeset.unselected.len(), 1);
+    assert!(changeset.diff.contains("+changed 2"));
+    assert!(!changeset.diff.contains("changed 19"));
+    assert!(!changeset.diff.contains("b.txt"));
+
+    // only the chosen hunk is staged
+    stage(repo_path, &changeset).unwrap();
+    let staged = indexed(&repo, "a.txt").unwrap();
+    assert!(staged.contains("changed 2\n"));
+    assert!(staged.contains("line 19\n"));
+    assert_eq!(indexed(&repo, "b.txt"), None);
+}
+
+#[test]
+fn test_selected_hunks_staged() {
+    let (temp_dir, repo) = setup_test_repo();
+    let repo_path = temp_dir.path();
+    let selection = setup_two_hunks(&repo, repo_path);
+
+    // stage everything, then commit only the chosen hunk
+    let mut index = repo.index().unwrap();
+    index
+        .add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)
+        .unwrap();
+    index.write().unwrap();
+
+    let changeset = get_changes(
+        repo_path,
+        crate::constants::DEFAULT_CONTEXT,
+        Scope::Auto,
+        &[],
+        &[],
+        Some(&selection),
+    )
+    .unwrap()
+    .unwrap();
+    assert!(changeset.is_staged);
+    assert!(!changeset.diff.contains("changed 19"));
+
+    stage(repo_path, &changeset).unwrap();
+    let staged = indexed(&repo, "a.txt").unwrap();
+    assert!(staged.contains("changed 2\n"));
+    assert!(staged.contains("line 19\n"));
+    assert_eq!(indexed(&repo, "b.txt"), None);
+
+    // unstaged changes are left in the working directory
+    let contents = fs::read_to_string(repo_path.join("a.txt")).unwrap();
+    assert!(contents.contains("changed 19\n"));
+    assert!(repo_path.join("b.txt").exists());
+}
+
+#[test]
+fn test_scope() {
+    let (temp_dir, repo) = setup_test_repo();
+    let repo_path = temp_dir.path();
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        create_file(&repo_path.join(name), "one\n");
+    }
+    commit_all(&repo, "initial commit");
+
+    // a is staged, b is unstaged, c is both, and d is untracked
+    create_file(&repo_path.join("a.txt"), "two\n");
+    create_file(&repo_path.join("c.txt"), "two\n");
+    let mut index = repo.index().unwrap();
+    index.add_path(Path::new("a.txt")).unwrap();
+    index.add_path(Path::new("c.txt")).unwrap();
+    index.write().unwrap();
+    create_file(&repo_path.join("b.txt"), "two\n");
+    create_file(&repo_path.join("c.txt"), "three\n");
+    create_file(&repo_path.join("d.txt"), "new\n");
+
+    let sources = |files: &[FileChange]| -> Vec<(String, Source)> {
+        files.iter().map(|f| (f.path.clone(), f.source)).collect()
+    };
+    let get = |scope| {
+        get_changes(
+            repo_path,
+            crate::constants::DEFAULT_CONTEXT,
+            scope,
+            &[],
+            &[],
+            None,
+        )
+        .unwrap()
+        .unwrap()
+    };
+
+    // by default, only staged changes are committed
+    let changeset = get(Scope::Auto);
+    assert!(changeset.is_staged);
+    assert_eq!(
+        sources(&changeset.files),
+        [
+            (String::from("a.txt"), Source::Staged),
+            (String::from("c.txt"), Source::Staged)
+        ]
+    );
+    assert_eq!(
+        sources(&changeset.excluded),
+        [
+            (String::from("b.txt"), Source::Unstaged),
+            (String::from("c.txt"), Source::Unstaged),
+            (String::from("d.txt"), Source::Untracked)
+        ]
+    );
+
+    let changeset = get(Scope::Tracked);
+    assert_eq!(changeset.source(), "staged and unstaged changes");
+    assert_eq!(
+        sources(&changeset.files),
+        [
+            (String::from("a.txt"), Source::Staged),
+            (String::from("b.txt"), Source::Unstaged),
+            (String::from("c.txt"), Source::Both)
+        ]
+    );
+    assert_eq!(
+        sources(&changeset.excluded),
+        [(String::from("d.txt"), Source::Untracked)]
+    );
+    assert!(changeset.diff.contains("+three"));
+
+    let changeset = get(Scope::All);
+    assert_eq!(changeset.files.len(), 4);
+    assert_eq!(changeset.files[3].source, Source::Untracked);
+    assert!(changeset.excluded.is_empty());
+
+    // staged changes which weren't chosen are unstaged
+    let mut selection = Selection::default();
+    selection.files.insert(String::from("b.txt"), None);
+    selection.files.insert(String::from("c.txt"), None);
+    let changeset = get_changes(
+        repo_path,
+        crate::constants::DEFAULT_CONTEXT,
+        Scope::Tracked,
+        &[],
+        &[],
+        Some(&selection),
+    )
+    .unwrap()
+    .unwrap();
+    stage(repo_path, &changeset).unwrap();
+    assert_eq!(indexed(&repo, "a.txt").as_deref(), Some("one\n"));
+    assert_eq!(indexed(&repo, "b.txt").as_deref(), Some("two\n"));
+    assert_eq!(indexed(&repo, "c.txt").as_deref(), Some("three\n"));
+    assert_eq!(indexed(&repo, "d.txt"), None);
+}
+
+#[test]
+fn test_pathspecs() {
+    let (temp_dir, repo) = setup_test_repo();
+    let repo_path = temp_dir.path();
+    fs::create_dir(repo_path.join("src")).unwrap();
+    fs::create_dir(repo_path.join("tests")).unwrap();
+    for name in ["src/a.txt", "tests/b.txt", "c.txt"] {
+        create_file(&repo_path.join(name), "one\n");
+    }
+    commit_all(&repo, "initial commit");
+
+    // pathspecs are relative to the current directory
+    let resolve = |dir: &str, pathspecs: &[&str]| {
+        let pathspecs: Vec<String> = pathspecs.iter().map(ToString::to_string).collect();
+        resolve_pathspecs(&repo_path.join(dir), &pathspecs)
+    };
+    assert_eq!(
+        resolve("src", &["a.txt", "../tests/", "*.rs"]).unwrap(),
+        ["src/a.txt", "tests", "src/*.rs"]
+    );
+    assert_eq!(resolve("", &["."]).unwrap(), ["*"]);
+    assert!(resolve("src", &["../.."]).is_err());
+
+    // src/a.txt and c.txt are staged, tests/b.txt isn't
+    create_file(&repo_path.join("src/a.txt"), "two\n");
+    create_file(&repo_path.join("c.txt"), "two\n");
+    let mut index = repo.index().unwrap();
+    index.add_path(Path::new("src/a.txt")).unwrap();
+    index.add_path(Path::new("c.txt")).unwrap();
+    index.write().unwrap();
+    create_file(&repo_path.join("tests/b.txt"), "two\n");
+
+    let get = |pathspec: &str| {
+        get_changes(
+            repo_path,
+            crate::constants::DEFAULT_CONTEXT,
+            Scope::Auto,
+            &[String::from(pathspec)],
+            &[],
+            None,
+        )
+        .unwrap()
+        .unwrap()
+    };
+    let paths = |changeset: &ChangeSet| -> Vec<String> {
+        changeset.files.iter().map(|f| f.path.clone()).collect()
+    };
+    let changeset = get("tests");
+    assert!(!changeset.is_staged);
+    assert_eq!(paths(&changeset), ["tests/b.txt"]);
+
+    // only matching staged changes are committed; the rest stay staged
+    let changeset = get("src");
+    assert!(changeset.is_staged);
+    assert_eq!(paths(&changeset), ["src/a.txt"]);
+    assert!(!changeset.diff.contains("c.txt"));
+    stage(repo_path, &changeset).unwrap();
+    commit(repo_path, "update a", &changeset).unwrap();
+
+    let tree = repo.head().unwrap().peel_to_tree().unwrap();
+    let committed = |path: &str| {
+        let entry = tree.get_path(Path::new(path)).unwrap();
+        let blob = repo.find_blob(entry.id()).unwrap();
+        String::from_utf8(blob.content().to_vec()).unwrap()
+    };
+    assert_eq!(committed("src/a.txt"), "two\n");
+    assert_eq!(committed("c.txt"), "one\n");
+    assert_eq!(indexed(&repo, "c.txt").as_deref(), Some("two\n"));
+    assert_eq!(indexed(&repo, "tests/b.txt").as_deref(), Some("one\n"));
+}
+
+#[test]
+fn test_amend() {
+    let (temp_dir, repo) = setup_test_repo();
+    let repo_path = temp_dir.path();
+    let error = get_amend_changes(repo_path, crate::constants::DEFAULT_CONTEXT, &[]).unwrap_err();
+    assert_eq!(error.to_string(), "no commit to amend");
+
+    // the root commit is compared against the empty tree
+    create_file(&repo_path.join("a.txt"), "one\n");
+    commit_all(&repo, "initial commit");
+    let changeset = get_amend_changes(repo_path, crate::constants::DEFAULT_CONTEXT, &[])
+        .unwrap()
+        .unwrap();
+    assert_eq!(changeset.files.len(), 1);
+    assert_eq!(changeset.files[0].status, Delta::Added);
+
+    // HEAD's changes and staged changes are included, unstaged changes aren't
+    create_file(&repo_path.join("a.txt"), "two\n");
+    commit_all(&repo, "bad message");
+    create_file(&repo_path.join("b.txt"), "staged\n");
+    let mut index = repo.index().unwrap();
+    index.add_path(Path::new("b.txt")).unwrap();
+    index.write().unwrap();
+    create_file(&repo_path.join("c.txt"), "unstaged\n");
+
+    let changeset = get_amend_changes(repo_path, crate::constants::DEFAULT_CONTEXT, &[])
+        .unwrap()
+        .unwrap();
+    assert!(changeset.is_amend);
+    let paths: Vec<&str> = changeset.files.iter().map(|f| f.path.as_str()).collect();
+    assert_eq!(paths, ["a.txt", "b.txt"]);
+    assert!(changeset.diff.contains("-one\n+two"));
+
+    // amending replaces HEAD
+    commit(repo_path, "update a and add b", &changeset).unwrap();
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.summary(), Some("update a and add b"));
+    assert_eq!(head.parent(0).unwrap().summary(), Some("initial commit"));
+    assert!(head.tree().unwrap().get_path(Path::new("b.txt")).is_ok());
+}
+
+#[test]
+fn test_reword() {
+    let (temp_dir, repo) = setup_test_repo();
+    let repo_path = temp_dir.path();
+    create_file(&repo_path.join("a.txt"), "one\n");
+    commit_all(&repo, "initial commit");
+    create_file(&repo_path.join("a.txt"), "two\n");
+    commit_all(&repo, "wip");
+    create_file(&repo_path.join("b.txt"), "three\n");
+    commit_all(&repo, "more wip");
+    let old_head = repo.head().unwrap().target().unwrap();
+
+    // a single revision means the commits after it
+    let commits = commits_in_range(repo_path, "HEAD~2").unwrap();
+    let messages: Vec<&str> = commits.iter().map(|(_, m)| m.as_str()).collect();
+    assert_eq!(messages, ["wip", "more wip"]);
+    assert_eq!(
+        commits_in_range(repo_path, "HEAD~2..HEAD").unwrap(),
+        commits
+    );
+    let error = commits_in_range(repo_path, "HEAD~2..HEAD~1").unwrap_err();
+    assert_eq!(error.to_string(), "range HEAD~2..HEAD~1 must end at HEAD");
+    assert!(commits_in_range(repo_path, "HEAD..HEAD").is_err());
+
+    let changeset = get_commit_changes(
+        repo_path,
+        commits[1].0,
+        crate::constants::DEFAULT_CONTEXT,
+        &[],
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(changeset.files.len(), 1);
+    assert_eq!(changeset.files[0].path, "b.txt");
+    assert!(changeset.source().starts_with("commit "));
+
+    // nothing changes if every message is kept
+    let kept: Vec<_> = commits.iter().map(|(oid, _)| (*oid, None)).collect();
+    assert_eq!(reword(repo_path, &kept).unwrap(), None);
+    assert_eq!(repo.head().unwrap().target(), Some(old_head));
+
+    // leading commits with kept messages aren't rewritten
+    let messages = [
+        (commits[0].0, None),
+        (commits[1].0, Some(String::from("add b"))),
+    ];
+    let backup = reword(repo_path, &messages).unwrap().unwrap();
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.message(), Some("add b\n"));
+    assert_eq!(head.parent_id(0).unwrap(), commits[0].0);
+    assert_eq!(
+        head.tree_id(),
+        repo.find_commit(old_head).unwrap().tree_id()
+    );
+    assert_eq!(
+        backup,
+        format!("refs/git-auto-commit/backup/master-{}", short_id(old_head))
+    );
+    assert_eq!(
+        repo.find_reference(&backup).unwrap().target(),
+        Some(old_head)
+    );
+
+    // rewording again keeps the first backup
+    let reworded = head.id();
+    let commits = commits_in_range(repo_path, "HEAD~1").unwrap();
+    let messages = [(commits[0].0, Some(String::from("add b.txt")))];
+    let second = reword(repo_path, &messages).unwrap().unwrap();
+    assert_ne!(second, backup);
+    assert_eq!(
+        repo.find_reference(&second).unwrap().target(),
+        Some(reworded)
+    );
+    assert_eq!(
+        repo.find_reference(&backup).unwrap().target(),
+        Some(old_head)
+    );
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.message(), Some("add b.txt\n"));
+}
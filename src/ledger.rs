@@ -0,0 +1,177 @@
+use crate::config::Config;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const LEDGER_DIR_NAME: &str = "git-auto-commit";
+const LEDGER_FILE_NAME: &str = "ledger.jsonl";
+pub const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// a generated commit message, stored as a line of json in the ledger
+///
+/// entries are written as soon as they are generated so spend is never lost,
+/// and their outcome is appended as a separate line once it is known
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// identifies the generation; candidates generated together share an id
+    pub id: String,
+    /// seconds since the unix epoch
+    pub timestamp: u64,
+    /// path of the repository
+    pub repo: String,
+    /// provider's method, eg. "API"
+    pub backend: String,
+    pub model: String,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_read_tokens: u64,
+    pub cache_write_tokens: u64,
+    /// cost in USD, if known
+    pub cost: Option<f64>,
+    /// what the user did with the generation; None if the run was interrupted
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub outcome: Option<Outcome>,
+}
+
+/// what the user did with a generation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    /// committed as generated
+    Accepted,
+    /// committed after being edited
+    Edited,
+    /// replaced by another generation
+    Rerolled,
+    /// the user aborted without committing
+    Rejected,
+}
+
+impl Outcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Accepted => "accepted",
+            Outcome::Edited => "edited",
+            Outcome::Rerolled => "rerolled",
+            Outcome::Rejected => "rejected",
+        }
+    }
+}
+
+/// a line in the ledger
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Line {
+    Entry(Entry),
+    Outcome { id: String, outcome: Outcome },
+}
+
+/// a new id for a generation
+pub fn new_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    format!("{nanos:x}-{:x}", std::process::id())
+}
+
+/// path of the ledger file, if one can be determined
+fn path(config: &Config) -> Option<PathBuf> {
+    config
+        .ledger_file
+        .clone()
+        .or_else(|| dirs::data_dir().map(|dir| dir.join(LEDGER_DIR_NAME).join(LEDGER_FILE_NAME)))
+}
+
+/// append entries to the ledger
+pub fn record(config: &Config, entries: &[Entry]) -> Result<()> {
+    append(
+        config,
+        entries.iter().map(|entry| Line::Entry(entry.clone())),
+    )
+}
+
+/// record the outcome of a generation
+pub fn resolve(config: &Config, id: &str, outcome: Outcome) -> Result<()> {
+    append(
+        config,
+        std::iter::once(Line::Outcome {
+            id: id.to_string(),
+            outcome,
+        }),
+    )
+}
+
+fn append(config: &Config, lines: impl Iterator<Item = Line>) -> Result<()> {
+    let Some(path) = path(config) else {
+        return Ok(());
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| anyhow::anyhow!("failed to create {}: {e}", dir.display()))?;
+    }
+
+    let mut contents = String::new();
+    for line in lines {
+        let line = serde_json::to_string(&line)
+            .map_err(|e| anyhow::anyhow!("failed to serialise ledger entry: {e}"))?;
+        contents.push_str(&line);
+        contents.push('\n');
+    }
+
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|e| anyhow::anyhow!("failed to write {}: {e}", path.display()))
+}
+
+/// all entries in the ledger, with their outcomes; unreadable lines are skipped
+pub fn read(config: &Config) -> Result<Vec<Entry>> {
+    let Some(path) = path(config).filter(|path| path.exists()) else {
+        return Ok(Vec::new());
+    };
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("failed to read {}: {e}", path.display()))?;
+
+    let mut entries = Vec::new();
+    let mut outcomes = HashMap::new();
+    for line in contents.lines() {
+        match serde_json::from_str(line) {
+            Ok(Line::Entry(entry)) => entries.push(entry),
+            Ok(Line::Outcome { id, outcome }) => {
+                outcomes.insert(id, outcome);
+            }
+            Err(_) => {}
+        }
+    }
+    for entry in &mut entries {
+        if let Some(outcome) = outcomes.get(&entry.id) {
+            entry.outcome = Some(*outcome);
+        }
+    }
+    Ok(entries)
+}
+
+/// total cost in USD of generations since midnight UTC
+pub fn spent_today(config: &Config) -> Result<f64> {
+    let today = now() / SECS_PER_DAY;
+    Ok(read(config)?
+        .iter()
+        .filter(|entry| entry.timestamp / SECS_PER_DAY == today)
+        .filter_map(|entry| entry.cost)
+        .sum())
+}
+
+/// seconds since the unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests;
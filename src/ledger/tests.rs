@@ -0,0 +1,87 @@
+use super::*;
+use tempfile::TempDir;
+
+/// helper to create a config using a temporary ledger
+fn setup() -> (TempDir, Config) {
+    let dir = TempDir::new().unwrap();
+    let config = Config {
+        ledger_file: Some(dir.path().join("ledger.jsonl")),
+        ..Config::default()
+    };
+    (dir, config)
+}
+
+/// helper to create an entry
+fn entry(id: &str, timestamp: u64, cost: Option<f64>) -> Entry {
+    Entry {
+        id: id.to_string(),
+        timestamp,
+        repo: "/repo".to_string(),
+        backend: "API".to_string(),
+        model: "claude-haiku".to_string(),
+        input_tokens: 100,
+        output_tokens: 10,
+        cache_read_tokens: 0,
+        cache_write_tokens: 0,
+        cost,
+        outcome: None,
+    }
+}
+
+#[test]
+fn test_empty() {
+    let (_dir, config) = setup();
+    assert!(read(&config).unwrap().is_empty());
+    assert!(spent_today(&config).unwrap().abs() < f64::EPSILON);
+}
+
+#[test]
+fn test_spent_today() {
+    let (_dir, config) = setup();
+
+    record(
+        &config,
+        &[
+            entry("a", now(), Some(0.25)),
+            entry("a", now(), None),
+            entry("b", now() - SECS_PER_DAY, Some(10.0)),
+        ],
+    )
+    .unwrap();
+    record(&config, &[entry("c", now(), Some(0.5))]).unwrap();
+
+    // only today's costs are included
+    assert_eq!(read(&config).unwrap().len(), 4);
+    assert!((spent_today(&config).unwrap() - 0.75).abs() < 1e-9);
+}
+
+#[test]
+fn test_outcomes() {
+    let (dir, config) = setup();
+
+    record(&config, &[entry("a", 1, None), entry("a", 1, None)]).unwrap();
+    record(&config, &[entry("b", 2, None)]).unwrap();
+    record(&config, &[entry("c", 3, None)]).unwrap();
+    resolve(&config, "a", Outcome::Rerolled).unwrap();
+    resolve(&config, "b", Outcome::Edited).unwrap();
+
+    // unreadable lines are skipped
+    let path = dir.path().join("ledger.jsonl");
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::write(&path, contents + "not json\n").unwrap();
+
+    let outcomes: Vec<_> = read(&config)
+        .unwrap()
+        .iter()
+        .map(|entry| entry.outcome)
+        .collect();
+    assert_eq!(
+        outcomes,
+        [
+            Some(Outcome::Rerolled),
+            Some(Outcome::Rerolled),
+            Some(Outcome::Edited),
+            None
+        ]
+    );
+}
@@ -0,0 +1,107 @@
+use crate::claude::ClaudeResponse;
+use crate::config::Config;
+use serde::Deserialize;
+
+/// prices in USD per million tokens
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Price {
+    pub input: f64,
+    pub output: f64,
+    #[serde(default)]
+    pub cache_read: f64,
+    #[serde(default)]
+    pub cache_write: f64,
+}
+
+/// built-in prices, keyed by api model id
+const BUILT_IN: &[(&str, Price)] = &[
+    (
+        "claude-3-5-haiku-20241022",
+        Price {
+            input: 0.80,
+            output: 4.00,
+            cache_read: 0.08,
+            cache_write: 1.00,
+        },
+    ),
+    (
+        "claude-3-7-sonnet-20250219",
+        Price {
+            input: 3.00,
+            output: 15.00,
+            cache_read: 0.30,
+            cache_write: 3.75,
+        },
+    ),
+    (
+        "claude-haiku-4-5",
+        Price {
+            input: 1.00,
+            output: 5.00,
+            cache_read: 0.10,
+            cache_write: 1.25,
+        },
+    ),
+    (
+        "claude-sonnet-4-5",
+        Price {
+            input: 3.00,
+            output: 15.00,
+            cache_read: 0.30,
+            cache_write: 3.75,
+        },
+    ),
+    (
+        "claude-opus-4-1",
+        Price {
+            input: 15.00,
+            output: 75.00,
+            cache_read: 1.50,
+            cache_write: 18.75,
+        },
+    ),
+];
+
+/// price for a model id, from config or the built-in table
+pub fn price<'a>(config: &'a Config, model_id: &str) -> Option<&'a Price> {
+    config.pricing.get(model_id).or_else(|| {
+        BUILT_IN
+            .iter()
+            .find(|(id, _)| *id == model_id)
+            .map(|(_, price)| price)
+    })
+}
+
+impl Price {
+    /// cost in USD of the given token counts
+    #[allow(clippy::cast_precision_loss)]
+    fn total(&self, input: u64, output: u64, cache_read: u64, cache_write: u64) -> f64 {
+        let total = input as f64 * self.input
+            + output as f64 * self.output
+            + cache_read as f64 * self.cache_read
+            + cache_write as f64 * self.cache_write;
+        total / 1_000_000.0
+    }
+}
+
+/// cost in USD of a response, if the model's price is known
+pub fn cost(config: &Config, model_id: &str, response: &ClaudeResponse) -> Option<f64> {
+    Some(price(config, model_id)?.total(
+        response.input_tokens,
+        response.output_tokens,
+        response.cache_read_tokens,
+        response.cache_write_tokens,
+    ))
+}
+
+/// projected cost in USD of a request, if the model's price is known; assumes
+/// nothing is read from the cache
+pub fn estimate(
+    config: &Config,
+    model_id: &str,
+    input_tokens: u64,
+    output_tokens: u64,
+) -> Option<f64> {
+    Some(price(config, model_id)?.total(input_tokens, output_tokens, 0, 0))
+}
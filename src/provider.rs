@@ -0,0 +1,508 @@
+mod claude_api;
+mod claude_cli;
+mod command;
+mod ollama;
+mod openai;
+mod replay;
+
+use crate::claude::{ClaudeResponse, Prompt};
+use crate::config::Config;
+use crate::constants::{CANCEL_POLL_MS, RETRY_INITIAL_DELAY_MS, RETRY_MAX_DELAY_SECS};
+use crate::context::AppContext;
+use crate::ui::Progress;
+use anyhow::{Result, bail};
+use colored::Colorize;
+use serde::Serialize;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use wait_timeout::ChildExt;
+
+/// a backend which turns a prompt into a commit message
+///
+/// providers are shared between threads when generating several candidates
+pub trait Provider: Send + Sync {
+    /// send the prompt and return the generated commit message
+    ///
+    /// backends which stream their output should pass it to `progress`, and
+    /// should stop with `Cancelled` when it is cancelled
+    fn generate(
+        &self,
+        ctx: &AppContext,
+        prompt: &Prompt,
+        progress: &Progress,
+    ) -> Result<ClaudeResponse>;
+
+    /// true if the backend returns a `CommitMessage` rather than free text, so
+    /// the prompt shouldn't ask for the message to be wrapped in backticks
+    fn structured_output(&self) -> bool {
+        false
+    }
+
+    /// true if the backend enables thinking itself when `think_hard` is set,
+    /// so the prompt shouldn't ask for it
+    fn native_thinking(&self) -> bool {
+        false
+    }
+
+    /// model id used to look up prices for cost estimates, if the backend is
+    /// paid for per token
+    fn model_id(&self, _ctx: &AppContext) -> Option<String> {
+        None
+    }
+}
+
+/// names accepted by `create`, for help and error messages
+pub const PROVIDER_NAMES: &[&str] = &[
+    "auto",
+    "claude-cli",
+    "claude-api",
+    "openai",
+    "ollama",
+    "command",
+    "replay",
+];
+
+/// create the provider with the given name
+///
+/// "auto" uses the api when an api-key is configured, otherwise the cli
+pub fn create(name: &str, config: &Config) -> Result<Box<dyn Provider>> {
+    match name {
+        "auto" => {
+            if config.api_key.is_some() {
+                create("claude-api", config)
+            } else {
+                create("claude-cli", config)
+            }
+        }
+        "claude-cli" => Ok(Box::new(claude_cli::ClaudeCli)),
+        "claude-api" => {
+            let Some(api_key) = &config.api_key else {
+                bail!("api-key is not configured");
+            };
+            Ok(Box::new(claude_api::ClaudeApi {
+                api_key: api_key.clone(),
+                base_url: config.claude_api.base_url.clone(),
+                headers: config.claude_api.headers.clone(),
+                thinking: config.claude_api.thinking,
+            }))
+        }
+        "openai" => {
+            let openai = &config.openai;
+            let Some(model_fast) = &openai.model_fast else {
+                bail!("openai.model-fast is not configured");
+            };
+            Ok(Box::new(openai::OpenAi {
+                base_url: openai.base_url.clone(),
+                api_key: openai.api_key.clone(),
+                model_fast: model_fast.clone(),
+                model_smart: openai.model_smart.as_ref().unwrap_or(model_fast).clone(),
+            }))
+        }
+        "ollama" => {
+            let ollama = &config.ollama;
+            let Some(model_fast) = &ollama.model_fast else {
+                bail!("ollama.model-fast is not configured");
+            };
+            Ok(Box::new(ollama::Ollama {
+                base_url: ollama.base_url.clone(),
+                model_fast: model_fast.clone(),
+                model_smart: ollama.model_smart.as_ref().unwrap_or(model_fast).clone(),
+            }))
+        }
+        "command" => {
+            let Some(command) = &config.command.command else {
+                bail!("command.command is not configured");
+            };
+            Ok(Box::new(command::ExternalCommand {
+                command: command.clone(),
+                json: config.command.json,
+            }))
+        }
+        "replay" => {
+            let replay = &config.replay;
+            let Some(dir) = &replay.dir else {
+                bail!("replay.dir is not configured");
+            };
+            let record = match replay.record.as_deref() {
+                Some("replay") => bail!("replay.record cannot be replay"),
+                Some(name) => Some(create(name, config)?),
+                None => None,
+            };
+            Ok(Box::new(replay::Replay {
+                dir: dir.clone(),
+                record,
+            }))
+        }
+        _ => bail!(
+            "unknown provider: {name} (expected one of: {})",
+            PROVIDER_NAMES.join(", ")
+        ),
+    }
+}
+
+/// create an http agent which gives up after the configured timeout
+///
+/// http error statuses are returned as responses so `send` can retry them
+fn http_agent(config: &Config) -> ureq::Agent {
+    let timeout = Duration::from_secs(config.timeout_secs);
+    let config = ureq::Agent::config_builder()
+        .timeout_global(Some(timeout))
+        .http_status_as_error(false)
+        .build();
+    config.into()
+}
+
+/// send a json request and return the response
+///
+/// `request` is called to build the request for each attempt.  rate-limit,
+/// overload and server errors and connection resets are retried with
+/// exponential backoff (or after the server's `retry-after`) until the
+/// configured retry deadline.  `name` identifies the backend in error messages
+///
+/// requests are abandoned if `progress` is cancelled
+fn send(
+    ctx: &AppContext,
+    name: &str,
+    request: impl Fn() -> ureq::RequestBuilder<ureq::typestate::WithBody>,
+    body: &impl Serialize,
+    progress: &Progress,
+) -> Result<ureq::http::Response<ureq::Body>> {
+    let deadline = Instant::now() + Duration::from_secs(ctx.config.retry_deadline_secs);
+    let body = serde_json::to_string(body)
+        .map_err(|e| anyhow::anyhow!("failed to serialise {name} request: {e}"))?;
+    let mut attempt = 0;
+
+    loop {
+        let request = request().header("content-type", "application/json");
+        let body = body.clone();
+        let response = cancellable(progress, move || request.send(body))?;
+
+        let (error, reason, retry_after) = match response {
+            Ok(resp) if resp.status().is_success() => return Ok(resp),
+            Ok(mut resp) => {
+                let status = resp.status().as_u16();
+                let retry_after = resp
+                    .headers()
+                    .get("retry-after")
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.trim().parse().ok())
+                    .map(Duration::from_secs);
+                let message = resp.body_mut().read_to_string().unwrap_or_default();
+                let error = anyhow::anyhow!("{name} api error: http {status}: {}", message.trim());
+                let Some(reason) = retry_reason(status) else {
+                    return Err(error);
+                };
+                (error, reason, retry_after)
+            }
+            Err(err) if is_connection_reset(&err) => {
+                (http_error(name, &err), "connection reset", None)
+            }
+            Err(err) => return Err(http_error(name, &err)),
+        };
+
+        attempt += 1;
+        let delay = retry_after.unwrap_or_else(|| backoff(attempt));
+        if Instant::now() + delay > deadline {
+            return Err(error);
+        }
+        progress.status(&format!(
+            "{name} {reason}, retrying in {}s (attempt {})",
+            delay.as_secs(),
+            attempt + 1
+        ));
+        sleep(delay, progress)?;
+    }
+}
+
+/// run `f` on another thread, failing with `Cancelled` if `progress` is
+/// cancelled before it returns
+///
+/// a cancelled thread is left to finish in the background, which allows a
+/// blocking http request to be abandoned
+fn cancellable<T: Send + 'static>(
+    progress: &Progress,
+    f: impl FnOnce() -> T + Send + 'static,
+) -> Result<T> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(f());
+    });
+    loop {
+        match rx.recv_timeout(Duration::from_millis(CANCEL_POLL_MS)) {
+            Ok(result) => return Ok(result),
+            Err(RecvTimeoutError::Timeout) => progress.check_cancelled()?,
+            Err(RecvTimeoutError::Disconnected) => bail!("request thread panicked"),
+        }
+    }
+}
+
+/// sleep for `duration`, failing with `Cancelled` if `progress` is cancelled
+fn sleep(duration: Duration, progress: &Progress) -> Result<()> {
+    let until = Instant::now() + duration;
+    loop {
+        progress.check_cancelled()?;
+        let remaining = until.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(());
+        }
+        std::thread::sleep(remaining.min(Duration::from_millis(CANCEL_POLL_MS)));
+    }
+}
+
+/// description of a retryable http status, or None if it should not be retried
+fn retry_reason(status: u16) -> Option<&'static str> {
+    match status {
+        429 => Some("rate limited"),
+        500 => Some("server error"),
+        529 => Some("overloaded"),
+        _ => None,
+    }
+}
+
+/// true if the connection was dropped by the server
+fn is_connection_reset(err: &ureq::Error) -> bool {
+    matches!(
+        err,
+        ureq::Error::Io(e) if matches!(
+            e.kind(),
+            std::io::ErrorKind::ConnectionReset | std::io::ErrorKind::ConnectionAborted
+        )
+    )
+}
+
+/// delay before the given retry: exponential, capped, with up to 50% jitter
+fn backoff(attempt: u32) -> Duration {
+    let delay = Duration::from_millis(RETRY_INITIAL_DELAY_MS)
+        .saturating_mul(2_u32.saturating_pow(attempt - 1))
+        .min(Duration::from_secs(RETRY_MAX_DELAY_SECS));
+    delay.mul_f64(1.0 - jitter() / 2.0)
+}
+
+/// random value in 0..1, used to spread out retries from concurrent clients
+#[allow(clippy::cast_precision_loss)]
+fn jitter() -> f64 {
+    // RandomState is randomly seeded, which avoids a dependency on rand
+    let random = RandomState::new().build_hasher().finish();
+    random as f64 / u64::MAX as f64
+}
+
+/// convert a ureq error into a user-facing error
+fn http_error(name: &str, err: &ureq::Error) -> anyhow::Error {
+    // check for timeout or other errors
+    let err_str = err.to_string();
+    if err_str.contains("timeout") || err_str.contains("deadline") {
+        anyhow::anyhow!("{name} thought for too long")
+    } else {
+        anyhow::anyhow!("{name} api error: {err}")
+    }
+}
+
+/// send a json request and return the response body
+///
+/// `name` identifies the backend in error messages
+fn send_json(
+    ctx: &AppContext,
+    name: &str,
+    request: impl Fn() -> ureq::RequestBuilder<ureq::typestate::WithBody>,
+    body: &impl Serialize,
+    progress: &Progress,
+) -> Result<String> {
+    let mut response = send(ctx, name, request, body, progress)?;
+
+    // read response body as string
+    let body = response
+        .body_mut()
+        .read_to_string()
+        .map_err(|e| anyhow::anyhow!("failed to read {name} api response: {e}"))?;
+
+    if ctx.debug_response {
+        let _ = writeln!(std::io::stdout(), "\n{}", body.dimmed());
+    }
+
+    Ok(body)
+}
+
+/// send a json request and pass each server-sent event's data to `on_event`
+///
+/// `name` identifies the backend in error messages
+fn send_sse(
+    ctx: &AppContext,
+    name: &str,
+    request: impl Fn() -> ureq::RequestBuilder<ureq::typestate::WithBody>,
+    body: &impl Serialize,
+    progress: &Progress,
+    mut on_event: impl FnMut(&str) -> Result<()>,
+) -> Result<()> {
+    let mut response = send(
+        ctx,
+        name,
+        || request().header("accept", "text/event-stream"),
+        body,
+        progress,
+    )?;
+
+    let reader = BufReader::new(response.body_mut().as_reader());
+    let mut raw = String::new();
+    for line in reader.lines() {
+        progress.check_cancelled()?;
+        let line = line.map_err(|e| {
+            let err_str = e.to_string();
+            if err_str.contains("timeout") || err_str.contains("deadline") {
+                anyhow::anyhow!("{name} thought for too long")
+            } else {
+                anyhow::anyhow!("failed to read {name} api response: {e}")
+            }
+        })?;
+        if ctx.debug_response {
+            raw.push_str(&line);
+            raw.push('\n');
+        }
+        if let Some(data) = line.strip_prefix("data:") {
+            on_event(data.trim_start())?;
+        }
+    }
+
+    if ctx.debug_response {
+        let _ = writeln!(std::io::stdout(), "\n{}", raw.trim_end().dimmed());
+    }
+
+    Ok(())
+}
+
+/// output of a process run by `run_command`
+struct CommandOutput {
+    status: ExitStatus,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+/// run a command with `input` on stdin, killing it after the configured timeout
+/// or when `progress` is cancelled
+///
+/// each line of stdout is passed to `on_line` as it is produced.  `name`
+/// identifies the backend in error messages
+fn run_command(
+    ctx: &AppContext,
+    name: &str,
+    command: &mut Command,
+    input: &str,
+    progress: &Progress,
+    mut on_line: impl FnMut(&str),
+) -> Result<CommandOutput> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("failed to spawn {name} process: {e}"))?;
+
+    // take stdin, stdout and stderr handles
+    let mut stdin = child
+        .stdin
+        .take()
+        .expect("failed to take stdin from child process");
+    let stdout = child
+        .stdout
+        .take()
+        .expect("failed to take stdout from child process");
+    let mut stderr = child
+        .stderr
+        .take()
+        .expect("failed to take stderr from child process");
+
+    // read output on separate threads so the process can't block on a full pipe
+    let (stdout_tx, stdout_rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).split(b'\n') {
+            if stdout_tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    let stderr_thread = std::thread::spawn(move || {
+        let mut stderr_data = Vec::new();
+        stderr.read_to_end(&mut stderr_data).map(|_| stderr_data)
+    });
+
+    // write input to stdin and close it, on its own thread too, so a process
+    // which doesn't read it can still be timed out or cancelled
+    let input = input.to_string();
+    let stdin_thread = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+
+    // read stdout until it is closed, then wait for the process to exit,
+    // checking for cancellation while waiting
+    let deadline = Instant::now() + Duration::from_secs(ctx.config.timeout_secs);
+    let poll = || {
+        deadline
+            .saturating_duration_since(Instant::now())
+            .min(Duration::from_millis(CANCEL_POLL_MS))
+    };
+    let mut stdout_data = Vec::new();
+    let mut stdout_open = true;
+    let mut status = None;
+    while Instant::now() < deadline && !progress.is_cancelled() {
+        if stdout_open {
+            match stdout_rx.recv_timeout(poll()) {
+                Ok(Ok(line)) => {
+                    on_line(&String::from_utf8_lossy(&line));
+                    stdout_data.extend_from_slice(&line);
+                    stdout_data.push(b'\n');
+                }
+                Ok(Err(e)) => {
+                    progress.warn(format!("failed to read {name} stdout: {e}"));
+                    stdout_open = false;
+                }
+                Err(RecvTimeoutError::Disconnected) => stdout_open = false,
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+        } else {
+            status = child
+                .wait_timeout(poll())
+                .map_err(|e| anyhow::anyhow!("failed to wait for {name} process: {e}"))?;
+            if status.is_some() {
+                break;
+            }
+        }
+    }
+
+    let Some(status) = status else {
+        // timed out or cancelled, kill the process
+        if let Err(e) = child.kill() {
+            progress.warn(format!("failed to kill {name} process: {e}"));
+        }
+        let _ = child.wait();
+        progress.check_cancelled()?;
+        bail!("{name} thought for too long")
+    };
+
+    // a process may exit without reading all its input; the thread is left
+    // if something else still holds stdin open
+    if stdin_thread.is_finished()
+        && let Ok(Err(e)) = stdin_thread.join()
+        && e.kind() != std::io::ErrorKind::BrokenPipe
+    {
+        bail!("failed to write to {name} stdin: {e}");
+    }
+
+    let stderr_data = match stderr_thread.join() {
+        Ok(Ok(data)) => data,
+        Ok(Err(e)) => {
+            progress.warn(format!("failed to read {name} stderr: {e}"));
+            Vec::new()
+        }
+        Err(_) => Vec::new(),
+    };
+
+    Ok(CommandOutput {
+        status,
+        stdout: stdout_data,
+        stderr: stderr_data,
+    })
+}
+
+#[cfg(test)]
+mod tests;
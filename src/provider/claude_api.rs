@@ -0,0 +1,427 @@
+use crate::claude::{
+    ClaudeResponse, CommitMessage, Prompt, extract_from_backticks, thinking_budget,
+};
+use crate::constants::CHARS_PER_TOKEN;
+use crate::context::AppContext;
+use crate::pricing;
+use crate::provider::{Provider, http_agent, send_json, send_sse};
+use crate::ui::Progress;
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// generate via the anthropic messages api
+pub struct ClaudeApi {
+    pub api_key: String,
+    pub base_url: String,
+    pub headers: BTreeMap<String, String>,
+    pub thinking: bool,
+}
+
+// api request/response structures
+#[derive(Serialize)]
+struct ApiRequest {
+    model: String,
+    max_tokens: u32,
+    system: Vec<ApiTextBlock>,
+    messages: Vec<ApiMessage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<ApiTool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<ApiToolChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking: Option<ApiThinking>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+#[derive(Serialize)]
+struct ApiMessage {
+    role: String,
+    content: Vec<ApiTextBlock>,
+}
+
+#[derive(Serialize)]
+struct ApiTextBlock {
+    #[serde(rename = "type")]
+    block_type: &'static str,
+    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_control: Option<CacheControl>,
+}
+
+impl ApiTextBlock {
+    fn new(text: &str) -> Self {
+        Self {
+            block_type: "text",
+            text: text.to_string(),
+            cache_control: None,
+        }
+    }
+
+    /// mark the prompt up to and including this block as cacheable
+    fn cached(text: &str) -> Self {
+        Self {
+            cache_control: Some(CacheControl {
+                cache_type: "ephemeral",
+            }),
+            ..Self::new(text)
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct CacheControl {
+    #[serde(rename = "type")]
+    cache_type: &'static str,
+}
+
+#[derive(Serialize)]
+struct ApiTool {
+    name: &'static str,
+    description: &'static str,
+    input_schema: serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct ApiToolChoice {
+    #[serde(rename = "type")]
+    choice_type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+struct ApiThinking {
+    #[serde(rename = "type")]
+    thinking_type: &'static str,
+    budget_tokens: u32,
+}
+
+/// name of the tool the model must call with the commit message
+const COMMIT_MESSAGE_TOOL: &str = "commit_message";
+
+/// tool used to return the message as structured output (see `CommitMessage`)
+fn commit_message_tool() -> ApiTool {
+    ApiTool {
+        name: COMMIT_MESSAGE_TOOL,
+        description: "Record the generated commit message.",
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "summary": {
+                    "type": "string",
+                    "description": "first line of the commit message, without type or scope"
+                },
+                "body": {
+                    "type": "array",
+                    "items": {"type": "string"},
+                    "description": "lines following the blank line after the summary; empty for single-line messages"
+                },
+                "type": {
+                    "type": "string",
+                    "description": "conventional commit type, eg. fix or feat"
+                },
+                "scope": {
+                    "type": "string",
+                    "description": "conventional commit scope"
+                }
+            },
+            "required": ["summary", "body"]
+        }),
+    }
+}
+
+#[derive(Deserialize)]
+struct ApiResponse {
+    content: Vec<ApiContent>,
+    usage: ApiUsage,
+}
+
+#[derive(Deserialize)]
+struct ApiContent {
+    #[serde(rename = "type")]
+    content_type: String,
+    name: Option<String>,
+    input: Option<serde_json::Value>,
+    text: Option<String>,
+    thinking: Option<String>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[allow(clippy::struct_field_names)]
+struct ApiUsage {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+    #[serde(default)]
+    cache_read_input_tokens: u64,
+    #[serde(default)]
+    cache_creation_input_tokens: u64,
+}
+
+// streaming (server-sent events) structures
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    MessageStart {
+        message: StreamMessage,
+    },
+    ContentBlockDelta {
+        delta: StreamDelta,
+    },
+    MessageDelta {
+        usage: ApiUsage,
+    },
+    Error {
+        error: StreamError,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct StreamMessage {
+    usage: ApiUsage,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamDelta {
+    InputJsonDelta {
+        partial_json: String,
+    },
+    TextDelta {
+        text: String,
+    },
+    ThinkingDelta {
+        thinking: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct StreamError {
+    message: String,
+}
+
+impl Provider for ClaudeApi {
+    fn generate(
+        &self,
+        ctx: &AppContext,
+        prompt: &Prompt,
+        progress: &Progress,
+    ) -> Result<ClaudeResponse> {
+        // map model names to api model ids
+        let model = ctx.config.model(ctx.model);
+        let model_id = ctx.config.claude_api.model_id(model);
+
+        // construct api request, with cache breakpoints after the rules and
+        // the diff so rerolls only pay full price for the instructions
+        let mut content = vec![ApiTextBlock::cached(&prompt.diff)];
+        if !prompt.instructions.is_empty() {
+            content.push(ApiTextBlock::new(&prompt.instructions));
+        }
+        // think natively rather than asking for it in the prompt.  forcing a
+        // tool call isn't supported with thinking, so the model may choose.
+        // free text prompts don't offer the tool at all
+        let thinking = (ctx.think_hard && self.thinking).then_some(ApiThinking {
+            thinking_type: "enabled",
+            budget_tokens: thinking_budget(ctx),
+        });
+        let tool_choice = if !prompt.structured {
+            None
+        } else if thinking.is_some() {
+            Some(ApiToolChoice {
+                choice_type: "auto",
+                name: None,
+            })
+        } else {
+            Some(ApiToolChoice {
+                choice_type: "tool",
+                name: Some(COMMIT_MESSAGE_TOOL),
+            })
+        };
+
+        let request_body = ApiRequest {
+            model: model_id.to_string(),
+            max_tokens: 1024 + thinking.as_ref().map_or(0, |t| t.budget_tokens),
+            system: vec![ApiTextBlock::cached(&prompt.system)],
+            messages: vec![ApiMessage {
+                role: "user".to_string(),
+                content,
+            }],
+            tools: if prompt.structured {
+                vec![commit_message_tool()]
+            } else {
+                Vec::new()
+            },
+            tool_choice,
+            thinking,
+            stream: ctx.config.stream,
+        };
+
+        let url = format!("{}/messages", self.base_url.trim_end_matches('/'));
+        let agent = http_agent(&ctx.config);
+        let request = || {
+            let mut request = agent
+                .post(&url)
+                .header("x-api-key", &self.api_key)
+                .header("anthropic-version", "2023-06-01");
+            for (name, value) in &self.headers {
+                request = request.header(name, value);
+            }
+            request
+        };
+
+        let (message, usage, thinking_chars) = if ctx.config.stream {
+            stream_response(ctx, prompt, request, &request_body, progress)?
+        } else {
+            let body = send_json(ctx, "claude", request, &request_body, progress)?;
+
+            // parse response
+            let api_response: ApiResponse = serde_json::from_str(&body)
+                .map_err(|e| anyhow::anyhow!("failed to parse claude api response: {e}"))?;
+
+            // thinking blocks are only counted
+            let thinking_chars = api_response
+                .content
+                .iter()
+                .filter_map(|c| c.thinking.as_ref())
+                .map(String::len)
+                .sum();
+
+            // extract the commit message from the tool call, if it was made
+            let input = api_response
+                .content
+                .iter()
+                .find(|c| {
+                    c.content_type == "tool_use" && c.name.as_deref() == Some(COMMIT_MESSAGE_TOOL)
+                })
+                .and_then(|c| c.input.clone());
+            let message = match input {
+                Some(input) if prompt.structured => render(ctx, serde_json::from_value(input))?,
+                _ => from_text(
+                    api_response
+                        .content
+                        .into_iter()
+                        .filter_map(|c| c.text)
+                        .collect(),
+                    progress,
+                )?,
+            };
+            (message, api_response.usage, thinking_chars)
+        };
+
+        let mut response = ClaudeResponse {
+            message,
+            method: String::from("API"),
+            model: model.to_string(),
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            cache_read_tokens: usage.cache_read_input_tokens,
+            cache_write_tokens: usage.cache_creation_input_tokens,
+            thinking_tokens: (thinking_chars / CHARS_PER_TOKEN) as u64,
+            cost: None,
+        };
+        response.cost = pricing::cost(&ctx.config, model_id, &response);
+        Ok(response)
+    }
+
+    fn structured_output(&self) -> bool {
+        true
+    }
+
+    fn native_thinking(&self) -> bool {
+        self.thinking
+    }
+
+    fn model_id(&self, ctx: &AppContext) -> Option<String> {
+        let model = ctx.config.model(ctx.model);
+        Some(ctx.config.claude_api.model_id(model).to_string())
+    }
+}
+
+/// the commit message from the tool call's input, validated and rendered
+fn render(ctx: &AppContext, input: serde_json::Result<CommitMessage>) -> Result<String> {
+    let message =
+        input.map_err(|e| anyhow::anyhow!("invalid commit message from claude api: {e}"))?;
+    message
+        .validate()
+        .map_err(|e| anyhow::anyhow!("invalid commit message from claude api: {e}"))?;
+    Ok(message.render(ctx.multi_line))
+}
+
+/// the message from the response's text, for free text prompts, and when the
+/// model answers in text rather than calling the tool, as it may when thinking
+fn from_text(text: String, progress: &Progress) -> Result<String> {
+    let message = extract_from_backticks(text, progress);
+    if message.is_empty() {
+        bail!("claude api response missing commit message");
+    }
+    Ok(message)
+}
+
+/// send a streaming request, previewing the message as it arrives
+fn stream_response(
+    ctx: &AppContext,
+    prompt: &Prompt,
+    request: impl Fn() -> ureq::RequestBuilder<ureq::typestate::WithBody>,
+    request_body: &ApiRequest,
+    progress: &Progress,
+) -> Result<(String, ApiUsage, usize)> {
+    let mut input = String::new();
+    let mut text = String::new();
+    let mut thinking_chars = 0;
+    let mut usage = ApiUsage::default();
+
+    send_sse(ctx, "claude", request, request_body, progress, |data| {
+        let event: StreamEvent = serde_json::from_str(data)
+            .map_err(|e| anyhow::anyhow!("failed to parse claude api event: {e}"))?;
+        match event {
+            StreamEvent::MessageStart { message } => usage = message.usage,
+            StreamEvent::ContentBlockDelta {
+                delta: StreamDelta::InputJsonDelta { partial_json },
+            } => {
+                input.push_str(&partial_json);
+                if let Some(message) = parse_partial(&input) {
+                    progress.preview(&message.render(ctx.multi_line));
+                }
+            }
+            StreamEvent::ContentBlockDelta {
+                delta: StreamDelta::TextDelta { text: delta },
+            } => {
+                text.push_str(&delta);
+                progress.preview(&text);
+            }
+            StreamEvent::ContentBlockDelta {
+                delta: StreamDelta::ThinkingDelta { thinking },
+            } => {
+                thinking_chars += thinking.len();
+                progress.status("thinking");
+            }
+            StreamEvent::MessageDelta { usage: delta } => {
+                usage.output_tokens = delta.output_tokens;
+            }
+            StreamEvent::Error { error } => bail!("claude api error: {}", error.message),
+            StreamEvent::ContentBlockDelta { .. } | StreamEvent::Other => {}
+        }
+        Ok(())
+    })?;
+
+    let message = if prompt.structured && !input.is_empty() {
+        render(ctx, serde_json::from_str(&input))?
+    } else {
+        from_text(text, progress)?
+    };
+    Ok((message, usage, thinking_chars))
+}
+
+/// best-effort parse of incomplete tool input, for previews
+fn parse_partial(input: &str) -> Option<CommitMessage> {
+    ["", "\"}", "]}", "\"]}", "}"]
+        .iter()
+        .find_map(|suffix| serde_json::from_str(&format!("{input}{suffix}")).ok())
+}
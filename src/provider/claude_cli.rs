@@ -0,0 +1,160 @@
+use crate::claude::{ClaudeResponse, Prompt, extract_from_backticks};
+use crate::context::AppContext;
+use crate::provider::{Provider, run_command};
+use crate::ui::Progress;
+use anyhow::{Result, bail};
+use colored::Colorize;
+use std::io::Write;
+use std::process::Command;
+use tempfile::TempDir;
+
+/// generate via the `claude` cli tool
+pub struct ClaudeCli;
+
+impl Provider for ClaudeCli {
+    fn generate(
+        &self,
+        ctx: &AppContext,
+        prompt: &Prompt,
+        progress: &Progress,
+    ) -> Result<ClaudeResponse> {
+        // set the cwd for claude to a known empty directory; this will
+        // prevent claude from unnecessarily reading project CLAUDE.md files
+        // and consuming tokens.  sadly we cannot prevent claude from reading
+        // user-level CLAUDE.md files
+        let temp_dir = TempDir::new()?;
+        let model = ctx.config.model(ctx.model);
+
+        // spawn claude process
+        let mut command = Command::new("claude");
+        command
+            .args(["--no-session-persistence"])
+            .args(["--print"])
+            .args(["--system-prompt", &prompt.system])
+            .args(["--tools", ""])
+            .args(["--model", model])
+            .current_dir(temp_dir.path());
+        if ctx.config.stream {
+            command
+                .args(["--output-format", "stream-json"])
+                .args(["--verbose"])
+                .args(["--include-partial-messages"]);
+        } else {
+            command.args(["--output-format", "json"]);
+        }
+
+        // preview text as it is streamed
+        let mut streamed = String::new();
+        let output = run_command(
+            ctx,
+            "claude",
+            &mut command,
+            &prompt.user(),
+            progress,
+            |line| {
+                if let Some(text) = stream_text(line) {
+                    streamed.push_str(&text);
+                    progress.preview(&streamed);
+                }
+            },
+        );
+        let _ = temp_dir.close();
+        let output = output?;
+
+        if !output.status.success() {
+            // claude reports some errors on stdout
+            let details: Vec<String> = [&output.stderr, &output.stdout]
+                .iter()
+                .map(|text| String::from_utf8_lossy(text).trim().to_string())
+                .filter(|text| !text.is_empty())
+                .collect();
+            bail!("claude failed ({}): {}", output.status, details.join("\n"));
+        }
+
+        let res = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+        if ctx.debug_response {
+            let _ = writeln!(std::io::stdout(), "\n{}", res.dimmed());
+        }
+
+        // parse json and extract result field, tokens, and cost; when streaming
+        // this is in the final "result" message
+        let json: serde_json::Value = if ctx.config.stream {
+            res.lines()
+                .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+                .find(|json| json.get("type").and_then(|v| v.as_str()) == Some("result"))
+                .ok_or_else(|| anyhow::anyhow!("claude stream missing result message"))?
+        } else {
+            serde_json::from_str(&res)
+                .map_err(|e| anyhow::anyhow!("failed to parse claude json response: {e}"))?
+        };
+
+        let output = json
+            .get("result")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("claude json response missing 'result' field"))?
+            .to_string();
+
+        // extract token usage
+        let usage = json.get("usage").and_then(|v| v.as_object());
+        let input_tokens = usage
+            .and_then(|u| u.get("input_tokens"))
+            .and_then(serde_json::Value::as_u64)
+            .unwrap_or(0);
+        let output_tokens = usage
+            .and_then(|u| u.get("output_tokens"))
+            .and_then(serde_json::Value::as_u64)
+            .unwrap_or(0);
+        let cache_read_tokens = usage
+            .and_then(|u| u.get("cache_read_input_tokens"))
+            .and_then(serde_json::Value::as_u64)
+            .unwrap_or(0);
+        let cache_write_tokens = usage
+            .and_then(|u| u.get("cache_creation_input_tokens"))
+            .and_then(serde_json::Value::as_u64)
+            .unwrap_or(0);
+
+        // extract total cost
+        let total_cost = json
+            .get("total_cost_usd")
+            .and_then(serde_json::Value::as_f64)
+            .unwrap_or(0.0);
+
+        let commit_message = extract_from_backticks(output, progress);
+
+        Ok(ClaudeResponse {
+            message: commit_message,
+            method: String::from("CLI"),
+            model: model.to_string(),
+            input_tokens,
+            output_tokens,
+            cache_read_tokens,
+            cache_write_tokens,
+            thinking_tokens: 0,
+            cost: Some(total_cost),
+        })
+    }
+
+    // the cli uses the same models as the api
+    fn model_id(&self, ctx: &AppContext) -> Option<String> {
+        let model = ctx.config.model(ctx.model);
+        Some(ctx.config.claude_api.model_id(model).to_string())
+    }
+}
+
+/// extract the text from a `stream-json` text delta line
+fn stream_text(line: &str) -> Option<String> {
+    let json: serde_json::Value = serde_json::from_str(line).ok()?;
+    if json.get("type")?.as_str()? != "stream_event" {
+        return None;
+    }
+    let event = json.get("event")?;
+    if event.get("type")?.as_str()? != "content_block_delta" {
+        return None;
+    }
+    let delta = event.get("delta")?;
+    if delta.get("type")?.as_str()? != "text_delta" {
+        return None;
+    }
+    Some(delta.get("text")?.as_str()?.to_string())
+}
@@ -0,0 +1,111 @@
+use crate::claude::{ClaudeResponse, Prompt, extract_from_backticks};
+use crate::context::{AppContext, ModelTier};
+use crate::provider::{Provider, run_command};
+use crate::ui::Progress;
+use anyhow::{Result, bail};
+use colored::Colorize;
+use serde::Deserialize;
+use std::io::Write;
+use std::process::Command;
+
+/// generate by piping the prompt into a user-configured shell command
+///
+/// the command receives the model tier ("fast" or "smart") in the
+/// `GIT_AUTO_COMMIT_MODEL_TIER` environment variable
+pub struct ExternalCommand {
+    pub command: String,
+    pub json: bool,
+}
+
+/// optional json envelope written to stdout by the command
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Envelope {
+    message: String,
+    model: Option<String>,
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+    #[serde(default)]
+    cache_read_tokens: u64,
+    #[serde(default)]
+    cache_write_tokens: u64,
+    cost: Option<f64>,
+}
+
+impl Provider for ExternalCommand {
+    fn generate(
+        &self,
+        ctx: &AppContext,
+        prompt: &Prompt,
+        progress: &Progress,
+    ) -> Result<ClaudeResponse> {
+        let tier = match ctx.model {
+            ModelTier::Fast => "fast",
+            ModelTier::Smart => "smart",
+        };
+
+        // run via shell to properly handle arguments in the command
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(&self.command)
+            .env("GIT_AUTO_COMMIT_MODEL_TIER", tier);
+
+        // preview plain text output as it is produced
+        let mut streamed = String::new();
+        let output = run_command(
+            ctx,
+            "command",
+            &mut command,
+            &prompt.text(),
+            progress,
+            |line| {
+                if !self.json {
+                    streamed.push_str(line);
+                    streamed.push('\n');
+                    progress.preview(&streamed);
+                }
+            },
+        )?;
+
+        let res = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+        if ctx.debug_response {
+            let _ = writeln!(std::io::stdout(), "\n{}", res.dimmed());
+        }
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("command failed ({}): {}", output.status, stderr.trim());
+        }
+
+        let envelope = if self.json {
+            serde_json::from_str(&res)
+                .map_err(|e| anyhow::anyhow!("failed to parse command json output: {e}"))?
+        } else {
+            Envelope {
+                message: res,
+                model: None,
+                input_tokens: 0,
+                output_tokens: 0,
+                cache_read_tokens: 0,
+                cache_write_tokens: 0,
+                cost: None,
+            }
+        };
+
+        Ok(ClaudeResponse {
+            message: extract_from_backticks(envelope.message, progress),
+            method: String::from("Command"),
+            model: envelope.model.unwrap_or_else(|| tier.to_string()),
+            input_tokens: envelope.input_tokens,
+            output_tokens: envelope.output_tokens,
+            cache_read_tokens: envelope.cache_read_tokens,
+            cache_write_tokens: envelope.cache_write_tokens,
+            thinking_tokens: 0,
+            cost: envelope.cost,
+        })
+    }
+}
@@ -0,0 +1,86 @@
+use crate::claude::{ClaudeResponse, Prompt, extract_from_backticks};
+use crate::context::{AppContext, ModelTier};
+use crate::provider::{Provider, http_agent, send_json};
+use crate::ui::Progress;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// generate via a local ollama server; nothing leaves the machine
+pub struct Ollama {
+    pub base_url: String,
+    pub model_fast: String,
+    pub model_smart: String,
+}
+
+// api request/response structures
+#[derive(Serialize)]
+struct ChatRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    stream: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChatMessage {
+    role: String,
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    message: ChatMessage,
+    #[serde(default)]
+    prompt_eval_count: u64,
+    #[serde(default)]
+    eval_count: u64,
+}
+
+impl Provider for Ollama {
+    fn generate(
+        &self,
+        ctx: &AppContext,
+        prompt: &Prompt,
+        progress: &Progress,
+    ) -> Result<ClaudeResponse> {
+        let model = match ctx.model {
+            ModelTier::Fast => &self.model_fast,
+            ModelTier::Smart => &self.model_smart,
+        };
+
+        // construct api request
+        let request_body = ChatRequest {
+            model: model.clone(),
+            messages: vec![
+                ChatMessage {
+                    role: "system".to_string(),
+                    content: prompt.system.clone(),
+                },
+                ChatMessage {
+                    role: "user".to_string(),
+                    content: prompt.user(),
+                },
+            ],
+            stream: false,
+        };
+
+        let url = format!("{}/api/chat", self.base_url.trim_end_matches('/'));
+        let agent = http_agent(&ctx.config);
+        let body = send_json(ctx, "ollama", || agent.post(&url), &request_body, progress)?;
+
+        // parse response
+        let chat_response: ChatResponse = serde_json::from_str(&body)
+            .map_err(|e| anyhow::anyhow!("failed to parse ollama api response: {e}"))?;
+
+        Ok(ClaudeResponse {
+            message: extract_from_backticks(chat_response.message.content, progress),
+            method: String::from("Ollama"),
+            model: model.clone(),
+            input_tokens: chat_response.prompt_eval_count,
+            output_tokens: chat_response.eval_count,
+            cache_read_tokens: 0,
+            cache_write_tokens: 0,
+            thinking_tokens: 0,
+            cost: None,
+        })
+    }
+}
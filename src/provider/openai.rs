@@ -0,0 +1,125 @@
+use crate::claude::{ClaudeResponse, Prompt, extract_from_backticks};
+use crate::context::{AppContext, ModelTier};
+use crate::pricing;
+use crate::provider::{Provider, http_agent, send_json};
+use crate::ui::Progress;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// generate via an openai-compatible chat completions api
+pub struct OpenAi {
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub model_fast: String,
+    pub model_smart: String,
+}
+
+// api request/response structures
+#[derive(Serialize)]
+struct ChatRequest {
+    model: String,
+    // reasoning models reject the older `max_tokens`
+    max_completion_tokens: u32,
+    messages: Vec<ChatMessage>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChatMessage {
+    role: String,
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+    usage: Option<ChatUsage>,
+}
+
+#[derive(Deserialize)]
+struct ChatChoice {
+    message: ChatMessage,
+}
+
+#[derive(Deserialize)]
+struct ChatUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
+}
+
+impl Provider for OpenAi {
+    fn generate(
+        &self,
+        ctx: &AppContext,
+        prompt: &Prompt,
+        progress: &Progress,
+    ) -> Result<ClaudeResponse> {
+        let model = match ctx.model {
+            ModelTier::Fast => &self.model_fast,
+            ModelTier::Smart => &self.model_smart,
+        };
+
+        // construct api request
+        let request_body = ChatRequest {
+            model: model.clone(),
+            max_completion_tokens: 1024,
+            messages: vec![
+                ChatMessage {
+                    role: "system".to_string(),
+                    content: Some(prompt.system.clone()),
+                },
+                ChatMessage {
+                    role: "user".to_string(),
+                    content: Some(prompt.user()),
+                },
+            ],
+        };
+
+        let url = format!("{}/chat/completions", self.base_url.trim_end_matches('/'));
+        let agent = http_agent(&ctx.config);
+        let request = || {
+            let request = agent.post(&url);
+            match &self.api_key {
+                Some(api_key) => request.header("authorization", &format!("Bearer {api_key}")),
+                None => request,
+            }
+        };
+        let body = send_json(ctx, "openai", request, &request_body, progress)?;
+
+        // parse response
+        let chat_response: ChatResponse = serde_json::from_str(&body)
+            .map_err(|e| anyhow::anyhow!("failed to parse openai api response: {e}"))?;
+
+        // extract text from the first choice
+        let output = chat_response
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|c| c.message.content)
+            .ok_or_else(|| anyhow::anyhow!("openai api response missing message content"))?;
+
+        let (input_tokens, output_tokens) = chat_response
+            .usage
+            .map_or((0, 0), |u| (u.prompt_tokens, u.completion_tokens));
+
+        let mut response = ClaudeResponse {
+            message: extract_from_backticks(output, progress),
+            method: String::from("OpenAI"),
+            model: model.clone(),
+            input_tokens,
+            output_tokens,
+            cache_read_tokens: 0,
+            cache_write_tokens: 0,
+            thinking_tokens: 0,
+            cost: None,
+        };
+        response.cost = pricing::cost(&ctx.config, model, &response);
+        Ok(response)
+    }
+
+    fn model_id(&self, ctx: &AppContext) -> Option<String> {
+        Some(match ctx.model {
+            ModelTier::Fast => self.model_fast.clone(),
+            ModelTier::Smart => self.model_smart.clone(),
+        })
+    }
+}
@@ -0,0 +1,135 @@
+use crate::claude::{ClaudeResponse, Prompt};
+use crate::context::AppContext;
+use crate::provider::Provider;
+use crate::ui::{Cancelled, Progress};
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// replay responses recorded as json fixtures, keyed by a hash of the model
+/// and prompt, so the workflow can be exercised without a network or the cli
+///
+/// when `record` is set, prompts without a fixture are sent to that provider
+/// and its response (or error) is saved for future runs
+pub struct Replay {
+    pub dir: PathBuf,
+    pub record: Option<Box<dyn Provider>>,
+}
+
+/// a recorded response
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Fixture {
+    /// the error message, if generation failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    message: String,
+    model: String,
+    input_tokens: u64,
+    output_tokens: u64,
+    cache_read_tokens: u64,
+    cache_write_tokens: u64,
+    thinking_tokens: u64,
+    cost: Option<f64>,
+}
+
+impl Provider for Replay {
+    fn generate(
+        &self,
+        ctx: &AppContext,
+        prompt: &Prompt,
+        progress: &Progress,
+    ) -> Result<ClaudeResponse> {
+        let model = ctx.config.model(ctx.model);
+        let path = fixture_path(&self.dir, model, prompt);
+
+        let fixture = if path.exists() {
+            read_fixture(&path)?
+        } else if let Some(record) = &self.record {
+            let fixture = match record.generate(ctx, prompt, progress) {
+                Ok(response) => Fixture {
+                    error: None,
+                    message: response.message,
+                    model: response.model,
+                    input_tokens: response.input_tokens,
+                    output_tokens: response.output_tokens,
+                    cache_read_tokens: response.cache_read_tokens,
+                    cache_write_tokens: response.cache_write_tokens,
+                    thinking_tokens: response.thinking_tokens,
+                    cost: response.cost,
+                },
+                // a cancelled generation says nothing about the prompt
+                Err(e) if e.is::<Cancelled>() => return Err(e),
+                Err(e) => Fixture {
+                    error: Some(e.to_string()),
+                    ..Fixture::default()
+                },
+            };
+            write_fixture(&path, &fixture)?;
+            fixture
+        } else {
+            bail!("no replay fixture {}", path.display());
+        };
+
+        if let Some(error) = fixture.error {
+            bail!(error);
+        }
+        Ok(ClaudeResponse {
+            message: fixture.message,
+            method: String::from("Replay"),
+            model: fixture.model,
+            input_tokens: fixture.input_tokens,
+            output_tokens: fixture.output_tokens,
+            cache_read_tokens: fixture.cache_read_tokens,
+            cache_write_tokens: fixture.cache_write_tokens,
+            thinking_tokens: fixture.thinking_tokens,
+            cost: fixture.cost,
+        })
+    }
+
+    // prompts must match the recording provider's, or fixtures won't be found
+    fn structured_output(&self) -> bool {
+        self.record.as_ref().is_some_and(|p| p.structured_output())
+    }
+
+    fn native_thinking(&self) -> bool {
+        self.record.as_ref().is_some_and(|p| p.native_thinking())
+    }
+
+    fn model_id(&self, ctx: &AppContext) -> Option<String> {
+        self.record.as_ref().and_then(|p| p.model_id(ctx))
+    }
+}
+
+/// path of the fixture for a model and prompt
+fn fixture_path(dir: &Path, model: &str, prompt: &Prompt) -> PathBuf {
+    let key = format!("{model}\0{}", prompt.text());
+    dir.join(format!("{:016x}.json", fnv1a(key.as_bytes())))
+}
+
+/// 64-bit fnv-1a hash; stable across platforms and releases, unlike `DefaultHasher`
+fn fnv1a(data: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+    data.iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(PRIME)
+    })
+}
+
+fn read_fixture(path: &Path) -> Result<Fixture> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("failed to read {}: {e}", path.display()))?;
+    serde_json::from_str(&contents)
+        .map_err(|e| anyhow::anyhow!("failed to parse {}: {e}", path.display()))
+}
+
+fn write_fixture(path: &Path, fixture: &Fixture) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| anyhow::anyhow!("failed to create {}: {e}", dir.display()))?;
+    }
+    let contents = serde_json::to_string_pretty(fixture)
+        .map_err(|e| anyhow::anyhow!("failed to serialise fixture: {e}"))?;
+    std::fs::write(path, contents + "\n")
+        .map_err(|e| anyhow::anyhow!("failed to write {}: {e}", path.display()))
+}
@@ -0,0 +1,743 @@
+use super::*;
+use crate::claude::Prompt;
+use crate::constants::{ULTRATHINK_BUDGET_TOKENS, ULTRATHINK_THRESHOLD};
+use crate::ui::Progress;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Read};
+use std::net::TcpListener;
+use std::thread::JoinHandle;
+
+/// a request received by the stub server
+struct StubRequest {
+    head: String,
+    body: String,
+}
+
+/// helper to start an http server which serves the given (status, body)
+/// responses, one per connection, and returns the requests it received
+///
+/// responses ask for an immediate retry so retry tests don't need to wait
+fn stub_server(responses: Vec<(u16, String)>) -> (String, JoinHandle<Vec<StubRequest>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    let handle = std::thread::spawn(move || {
+        let mut requests = Vec::new();
+        for (status, response_body) in responses {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+            // read headers
+            let mut head = String::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" || line.is_empty() {
+                    break;
+                }
+                head.push_str(&line);
+            }
+
+            // read body
+            let content_length = head
+                .lines()
+                .find_map(|l| {
+                    l.to_lowercase()
+                        .strip_prefix("content-length:")
+                        .map(|v| v.trim().parse::<usize>().unwrap())
+                })
+                .unwrap_or(0);
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {status} Stub\r\ncontent-type: application/json\r\n\
+                 retry-after: 0\r\ncontent-length: {}\r\nconnection: close\r\n\r\n\
+                 {response_body}",
+                response_body.len()
+            );
+            requests.push(StubRequest {
+                head,
+                body: String::from_utf8(body).unwrap(),
+            });
+        }
+        requests
+    });
+
+    (url, handle)
+}
+
+/// helper to format server-sent events
+fn sse_body(events: &[&str]) -> String {
+    events.iter().fold(String::new(), |mut body, data| {
+        let _ = write!(body, "event: x\ndata: {data}\n\n");
+        body
+    })
+}
+
+/// helper to compare a cost
+fn assert_cost(cost: Option<f64>, expected: f64) {
+    let cost = cost.expect("cost should be known");
+    assert!(
+        (cost - expected).abs() < 1e-9,
+        "cost {cost} should be {expected}"
+    );
+}
+
+/// helper to create a prompt
+fn prompt() -> Prompt {
+    Prompt {
+        system: "the rules".to_string(),
+        diff: "the diff".to_string(),
+        instructions: "the instructions".to_string(),
+        structured: true,
+    }
+}
+
+/// helper to create a context using the named provider
+fn context(name: &str, config: Config) -> AppContext {
+    let provider = create(name, &config).unwrap();
+    AppContext::new(config, provider, false, false)
+}
+
+#[test]
+fn test_unknown_provider() {
+    let result = create("nope", &Config::default());
+    assert!(result.is_err(), "unknown provider should be rejected");
+}
+
+#[test]
+fn test_openai() {
+    let (url, server) = stub_server(vec![(
+        200,
+        r#"{
+            "choices": [{"message": {"role": "assistant", "content": "```\nadd widget\n```"}}],
+            "usage": {"prompt_tokens": 120, "completion_tokens": 8}
+        }"#
+        .to_string(),
+    )]);
+
+    let mut config = Config::default();
+    config.openai.base_url = format!("{url}/v1/");
+    config.openai.api_key = Some("secret".to_string());
+    config.openai.model_fast = Some("local-model".to_string());
+    let ctx = context("openai", config);
+
+    let response = ctx
+        .provider
+        .generate(&ctx, &prompt(), &Progress::hidden())
+        .unwrap();
+    assert_eq!(response.message, "add widget");
+    assert_eq!(response.model, "local-model");
+    assert_eq!(response.input_tokens, 120);
+    assert_eq!(response.output_tokens, 8);
+
+    let requests = server.join().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].head.starts_with("POST /v1/chat/completions "));
+    assert!(
+        requests[0]
+            .head
+            .to_lowercase()
+            .contains("authorization: bearer secret")
+    );
+    let body: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+    assert_eq!(body["model"], "local-model");
+    assert_eq!(body["max_completion_tokens"], 1024);
+    assert!(body.get("max_tokens").is_none());
+    assert_eq!(body["messages"][0]["role"], "system");
+    assert_eq!(body["messages"][0]["content"], "the rules");
+    assert_eq!(
+        body["messages"][1]["content"],
+        "the diff\n\nthe instructions"
+    );
+}
+
+#[test]
+fn test_openai_error() {
+    let (url, server) = stub_server(vec![(400, r#"{"error": "bad"}"#.to_string())]);
+
+    let mut config = Config::default();
+    config.openai.base_url = url;
+    config.openai.model_fast = Some("local-model".to_string());
+    let ctx = context("openai", config);
+
+    let result = ctx.provider.generate(&ctx, &prompt(), &Progress::hidden());
+    assert!(result.is_err(), "http errors should be reported");
+    server.join().unwrap();
+}
+
+#[test]
+fn test_openai_requires_model() {
+    let result = create("openai", &Config::default());
+    assert!(result.is_err(), "openai without a model should be rejected");
+}
+
+#[test]
+fn test_ollama() {
+    let (url, server) = stub_server(vec![(
+        200,
+        r#"{
+            "model": "llama3",
+            "message": {"role": "assistant", "content": "```\nfix parser\n```"},
+            "done": true,
+            "prompt_eval_count": 300,
+            "eval_count": 12
+        }"#
+        .to_string(),
+    )]);
+
+    let mut config = Config::default();
+    config.ollama.base_url = url;
+    config.ollama.model_fast = Some("llama3".to_string());
+    config.ollama.model_smart = Some("llama3:70b".to_string());
+    let mut ctx = context("ollama", config);
+    ctx.model = crate::context::ModelTier::Smart;
+
+    let response = ctx
+        .provider
+        .generate(&ctx, &prompt(), &Progress::hidden())
+        .unwrap();
+    assert_eq!(response.message, "fix parser");
+    assert_eq!(response.model, "llama3:70b");
+    assert_eq!(response.input_tokens, 300);
+    assert_eq!(response.output_tokens, 12);
+
+    let requests = server.join().unwrap();
+    assert!(requests[0].head.starts_with("POST /api/chat "));
+    let body: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+    assert_eq!(body["model"], "llama3:70b");
+    assert_eq!(body["stream"], false);
+}
+
+#[test]
+fn test_command() {
+    let mut config = Config::default();
+    config.command.command = Some("tr a-z A-Z; echo; echo $GIT_AUTO_COMMIT_MODEL_TIER".to_string());
+    let ctx = context("command", config);
+
+    let response = ctx
+        .provider
+        .generate(
+            &ctx,
+            &Prompt {
+                system: String::new(),
+                diff: "``` hello ```".to_string(),
+                instructions: String::new(),
+                structured: false,
+            },
+            &Progress::hidden(),
+        )
+        .unwrap();
+    assert_eq!(response.message, "HELLO");
+    assert_eq!(response.model, "fast");
+    assert_eq!(response.cost, None);
+}
+
+#[test]
+fn test_command_json() {
+    let mut config = Config::default();
+    config.command.command = Some(
+        r#"cat > /dev/null; echo '{"message": "add x", "model": "foo", "input_tokens": 5, "output_tokens": 2, "cost": 0.5}'"#
+            .to_string(),
+    );
+    config.command.json = true;
+    let ctx = context("command", config);
+
+    let response = ctx
+        .provider
+        .generate(&ctx, &prompt(), &Progress::hidden())
+        .unwrap();
+    assert_eq!(response.message, "add x");
+    assert_eq!(response.model, "foo");
+    assert_eq!(response.input_tokens, 5);
+    assert_eq!(response.output_tokens, 2);
+    assert_eq!(response.cost, Some(0.5));
+}
+
+#[test]
+fn test_command_unread_input() {
+    let mut config = Config {
+        timeout_secs: 1,
+        ..Config::default()
+    };
+    config.command.command = Some("sleep 10".to_string());
+    let ctx = context("command", config);
+
+    // the input is larger than a pipe's buffer, and never read
+    let prompt = Prompt {
+        diff: "x".repeat(1 << 20),
+        ..prompt()
+    };
+    let start = Instant::now();
+    let error = ctx
+        .provider
+        .generate(&ctx, &prompt, &Progress::hidden())
+        .unwrap_err();
+    assert!(error.to_string().contains("too long"), "{error}");
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn test_command_failure() {
+    let mut config = Config::default();
+    config.command.command = Some("cat > /dev/null; echo oops >&2; exit 3".to_string());
+    let ctx = context("command", config);
+
+    let error = ctx
+        .provider
+        .generate(&ctx, &prompt(), &Progress::hidden())
+        .unwrap_err();
+    assert!(error.to_string().contains("oops"));
+}
+
+#[test]
+fn test_claude_api() {
+    let (url, server) = stub_server(vec![(
+        200,
+        r#"{
+            "content": [{
+                "type": "tool_use",
+                "name": "commit_message",
+                "input": {"summary": "update docs", "body": ["- ignored"]}
+            }],
+            "usage": {"input_tokens": 1000, "output_tokens": 10}
+        }"#
+        .to_string(),
+    )]);
+
+    let mut config = Config {
+        api_key: Some("key".to_string()),
+        stream: false,
+        ..Config::default()
+    };
+    config.claude_api.base_url = format!("{url}/proxy/v1");
+    config
+        .claude_api
+        .headers
+        .insert("x-proxy-token".to_string(), "token".to_string());
+    let ctx = context("claude-api", config);
+
+    let response = ctx
+        .provider
+        .generate(&ctx, &prompt(), &Progress::hidden())
+        .unwrap();
+    assert_eq!(response.message, "update docs");
+    assert_eq!(response.input_tokens, 1000);
+    assert_eq!(response.output_tokens, 10);
+    assert_cost(response.cost, 0.000_84);
+
+    let requests = server.join().unwrap();
+    let head = requests[0].head.to_lowercase();
+    assert!(head.starts_with("post /proxy/v1/messages "));
+    assert!(head.contains("x-api-key: key"));
+    assert!(head.contains("x-proxy-token: token"));
+    let body: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+    assert_eq!(body["model"], "claude-3-5-haiku-20241022");
+
+    // the rules and diff are cached, the instructions are not
+    let ephemeral = serde_json::json!({"type": "ephemeral"});
+    assert_eq!(body["system"][0]["text"], "the rules");
+    assert_eq!(body["system"][0]["cache_control"], ephemeral);
+    let content = &body["messages"][0]["content"];
+    assert_eq!(content[0]["text"], "the diff");
+    assert_eq!(content[0]["cache_control"], ephemeral);
+    assert_eq!(content[1]["text"], "the instructions");
+    assert!(content[1].get("cache_control").is_none());
+}
+
+#[test]
+fn test_claude_api_stream() {
+    let events = [
+        r#"{"type": "message_start", "message": {"usage": {"input_tokens": 900, "output_tokens": 1, "cache_read_input_tokens": 2000, "cache_creation_input_tokens": 100}}}"#,
+        r#"{"type": "content_block_start", "index": 0, "content_block": {"type": "tool_use", "name": "commit_message", "input": {}}}"#,
+        r#"{"type": "content_block_delta", "index": 0, "delta": {"type": "input_json_delta", "partial_json": "{\"summary\": \"refactor "}}"#,
+        r#"{"type": "content_block_delta", "index": 0, "delta": {"type": "input_json_delta", "partial_json": "parser\", \"type\": \"refactor\","}}"#,
+        r#"{"type": "content_block_delta", "index": 0, "delta": {"type": "input_json_delta", "partial_json": " \"body\": [\"- split lexer\"]}"}}"#,
+        r#"{"type": "content_block_stop", "index": 0}"#,
+        r#"{"type": "message_delta", "delta": {"stop_reason": "end_turn"}, "usage": {"output_tokens": 7}}"#,
+        r#"{"type": "message_stop"}"#,
+    ];
+    let (url, server) = stub_server(vec![(200, sse_body(&events))]);
+
+    let mut config = Config {
+        api_key: Some("key".to_string()),
+        ..Config::default()
+    };
+    config.claude_api.base_url = url;
+    let ctx = context("claude-api", config);
+
+    let response = ctx
+        .provider
+        .generate(&ctx, &prompt(), &Progress::hidden())
+        .unwrap();
+    assert_eq!(response.message, "refactor: refactor parser");
+    assert_eq!(response.input_tokens, 900);
+    assert_eq!(response.output_tokens, 7);
+    assert_eq!(response.cache_read_tokens, 2000);
+    assert_eq!(response.cache_write_tokens, 100);
+    // 900 * 0.80 + 7 * 4.00 + 2000 * 0.08 + 100 * 1.00 per million
+    assert_cost(response.cost, 0.001_008);
+
+    let requests = server.join().unwrap();
+    let body: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+    assert_eq!(body["stream"], true);
+    assert_eq!(body["tool_choice"]["name"], "commit_message");
+}
+
+#[test]
+fn test_claude_api_free_text() {
+    let events = [
+        r#"{"type": "message_start", "message": {"usage": {"input_tokens": 900, "output_tokens": 1}}}"#,
+        r#"{"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "```\n[{\"message\": "}}"#,
+        r#"{"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "\"add a\"}]\n```"}}"#,
+        r#"{"type": "message_stop"}"#,
+    ];
+    let (url, server) = stub_server(vec![(200, sse_body(&events))]);
+
+    let mut config = Config {
+        api_key: Some("key".to_string()),
+        ..Config::default()
+    };
+    config.claude_api.base_url = url;
+    let ctx = context("claude-api", config);
+
+    // the commit message tool isn't offered
+    let prompt = Prompt {
+        structured: false,
+        ..prompt()
+    };
+    let response = ctx
+        .provider
+        .generate(&ctx, &prompt, &Progress::hidden())
+        .unwrap();
+    assert_eq!(response.message, r#"[{"message": "add a"}]"#);
+
+    let requests = server.join().unwrap();
+    let body: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+    assert!(body.get("tools").is_none());
+    assert!(body.get("tool_choice").is_none());
+}
+
+#[test]
+fn test_claude_api_stream_error() {
+    let (url, server) = stub_server(vec![(
+        200,
+        sse_body(&[
+            r#"{"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}}"#,
+        ]),
+    )]);
+
+    let mut config = Config {
+        api_key: Some("key".to_string()),
+        ..Config::default()
+    };
+    config.claude_api.base_url = url;
+    let ctx = context("claude-api", config);
+
+    let error = ctx
+        .provider
+        .generate(&ctx, &prompt(), &Progress::hidden())
+        .unwrap_err();
+    assert!(error.to_string().contains("Overloaded"));
+    server.join().unwrap();
+}
+
+#[test]
+fn test_openai_pricing() {
+    let (url, server) = stub_server(vec![(
+        200,
+        r#"{
+            "choices": [{"message": {"role": "assistant", "content": "```\nadd widget\n```"}}],
+            "usage": {"prompt_tokens": 1000000, "completion_tokens": 1000000}
+        }"#
+        .to_string(),
+    )]);
+
+    let mut config = Config::default();
+    config.openai.base_url = url;
+    config.openai.model_fast = Some("gateway-model".to_string());
+    config.pricing.insert(
+        "gateway-model".to_string(),
+        crate::pricing::Price {
+            input: 1.0,
+            output: 2.0,
+            cache_read: 0.0,
+            cache_write: 0.0,
+        },
+    );
+    let ctx = context("openai", config);
+
+    let response = ctx
+        .provider
+        .generate(&ctx, &prompt(), &Progress::hidden())
+        .unwrap();
+    assert_cost(response.cost, 3.0);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_retry() {
+    let (url, server) = stub_server(vec![
+        (
+            529,
+            r#"{"error": {"type": "overloaded_error"}}"#.to_string(),
+        ),
+        (
+            429,
+            r#"{"error": {"type": "rate_limit_error"}}"#.to_string(),
+        ),
+        (
+            200,
+            r#"{
+                "content": [{
+                    "type": "tool_use",
+                    "name": "commit_message",
+                    "input": {"summary": "retry requests", "body": []}
+                }],
+                "usage": {"input_tokens": 10, "output_tokens": 2}
+            }"#
+            .to_string(),
+        ),
+    ]);
+
+    let mut config = Config {
+        api_key: Some("key".to_string()),
+        stream: false,
+        ..Config::default()
+    };
+    config.claude_api.base_url = url;
+    let ctx = context("claude-api", config);
+
+    let response = ctx
+        .provider
+        .generate(&ctx, &prompt(), &Progress::hidden())
+        .unwrap();
+    assert_eq!(response.message, "retry requests");
+    assert_eq!(server.join().unwrap().len(), 3);
+}
+
+#[test]
+fn test_retry_deadline() {
+    let (url, server) = stub_server(vec![(
+        529,
+        r#"{"error": {"type": "overloaded_error"}}"#.to_string(),
+    )]);
+
+    let mut config = Config {
+        api_key: Some("key".to_string()),
+        retry_deadline_secs: 0,
+        ..Config::default()
+    };
+    config.claude_api.base_url = url;
+    let ctx = context("claude-api", config);
+
+    let error = ctx
+        .provider
+        .generate(&ctx, &prompt(), &Progress::hidden())
+        .unwrap_err();
+    assert!(error.to_string().contains("overloaded_error"));
+    server.join().unwrap();
+}
+
+#[test]
+fn test_cancel() {
+    // a server which accepts the request but never responds
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut config = Config {
+        api_key: Some("key".to_string()),
+        ..Config::default()
+    };
+    config.claude_api.base_url = format!("http://{}", listener.local_addr().unwrap());
+    let ctx = context("claude-api", config);
+
+    let progress = Progress::hidden();
+    let start = Instant::now();
+    let error = std::thread::scope(|scope| {
+        scope.spawn(|| {
+            std::thread::sleep(Duration::from_millis(100));
+            progress.cancel();
+        });
+        ctx.provider
+            .generate(&ctx, &prompt(), &progress)
+            .unwrap_err()
+    });
+    assert!(error.is::<crate::ui::Cancelled>(), "{error}");
+    assert!(start.elapsed() < Duration::from_secs(ctx.config.timeout_secs));
+}
+
+#[test]
+fn test_backoff() {
+    assert!(backoff(1) <= Duration::from_millis(RETRY_INITIAL_DELAY_MS));
+    assert!(backoff(2) >= Duration::from_millis(RETRY_INITIAL_DELAY_MS));
+    assert!(backoff(30) <= Duration::from_secs(RETRY_MAX_DELAY_SECS));
+}
+
+#[test]
+fn test_claude_api_invalid_message() {
+    let (url, server) = stub_server(vec![(
+        200,
+        r#"{
+            "content": [{
+                "type": "tool_use",
+                "name": "commit_message",
+                "input": {"summary": "two\nlines", "body": []}
+            }],
+            "usage": {"input_tokens": 10, "output_tokens": 2}
+        }"#
+        .to_string(),
+    )]);
+
+    let mut config = Config {
+        api_key: Some("key".to_string()),
+        stream: false,
+        ..Config::default()
+    };
+    config.claude_api.base_url = url;
+    let ctx = context("claude-api", config);
+
+    let error = ctx
+        .provider
+        .generate(&ctx, &prompt(), &Progress::hidden())
+        .unwrap_err();
+    assert!(error.to_string().contains("multiple lines"));
+    server.join().unwrap();
+}
+
+#[test]
+fn test_claude_api_multi_line() {
+    let (url, server) = stub_server(vec![(
+        200,
+        r#"{
+            "content": [{
+                "type": "tool_use",
+                "name": "commit_message",
+                "input": {
+                    "summary": "add parser",
+                    "body": ["- tokenise input", "- build syntax tree"],
+                    "type": "feat",
+                    "scope": "lang"
+                }
+            }],
+            "usage": {"input_tokens": 10, "output_tokens": 2}
+        }"#
+        .to_string(),
+    )]);
+
+    let mut config = Config {
+        api_key: Some("key".to_string()),
+        stream: false,
+        ..Config::default()
+    };
+    config.claude_api.base_url = url;
+    let mut ctx = context("claude-api", config);
+    ctx.multi_line = true;
+
+    let response = ctx
+        .provider
+        .generate(&ctx, &prompt(), &Progress::hidden())
+        .unwrap();
+    assert_eq!(
+        response.message,
+        "feat(lang): add parser\n\n- tokenise input\n- build syntax tree"
+    );
+    server.join().unwrap();
+}
+
+#[test]
+fn test_claude_api_thinking() {
+    let events = [
+        r#"{"type": "message_start", "message": {"usage": {"input_tokens": 100, "output_tokens": 1}}}"#,
+        r#"{"type": "content_block_start", "index": 0, "content_block": {"type": "thinking", "thinking": ""}}"#,
+        r#"{"type": "content_block_delta", "index": 0, "delta": {"type": "thinking_delta", "thinking": "the diff renames a function"}}"#,
+        r#"{"type": "content_block_delta", "index": 0, "delta": {"type": "signature_delta", "signature": "abc"}}"#,
+        r#"{"type": "content_block_start", "index": 1, "content_block": {"type": "tool_use", "name": "commit_message", "input": {}}}"#,
+        r#"{"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"summary\": \"rename parse fn\", \"body\": []}"}}"#,
+        r#"{"type": "message_delta", "delta": {"stop_reason": "tool_use"}, "usage": {"output_tokens": 40}}"#,
+    ];
+    let (url, server) = stub_server(vec![(200, sse_body(&events))]);
+
+    let mut config = Config {
+        api_key: Some("key".to_string()),
+        ..Config::default()
+    };
+    config.claude_api.base_url = url;
+    let mut ctx = context("claude-api", config);
+    ctx.think_hard = true;
+    ctx.manual_reroll_count = ULTRATHINK_THRESHOLD + 1;
+
+    let response = ctx
+        .provider
+        .generate(&ctx, &prompt(), &Progress::hidden())
+        .unwrap();
+    assert_eq!(response.message, "rename parse fn");
+    assert_eq!(response.output_tokens, 40);
+    assert_eq!(response.thinking_tokens, 6);
+
+    let requests = server.join().unwrap();
+    let body: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+    assert_eq!(body["thinking"]["type"], "enabled");
+    assert_eq!(body["thinking"]["budget_tokens"], ULTRATHINK_BUDGET_TOKENS);
+    assert_eq!(body["tool_choice"]["type"], "auto");
+}
+
+#[test]
+fn test_claude_api_thinking_without_tool() {
+    // with thinking the model may answer in text rather than calling the tool
+    let events = [
+        r#"{"type": "message_start", "message": {"usage": {"input_tokens": 100, "output_tokens": 1}}}"#,
+        r#"{"type": "content_block_delta", "index": 0, "delta": {"type": "thinking_delta", "thinking": "hmm"}}"#,
+        r#"{"type": "content_block_delta", "index": 1, "delta": {"type": "text_delta", "text": "```\nrename parse fn\n```"}}"#,
+        r#"{"type": "message_delta", "delta": {"stop_reason": "end_turn"}, "usage": {"output_tokens": 40}}"#,
+    ];
+    let body = r#"{
+        "content": [
+            {"type": "thinking", "thinking": "hmm"},
+            {"type": "text", "text": "```\nrename parse fn\n```"}
+        ],
+        "usage": {"input_tokens": 100, "output_tokens": 40}
+    }"#;
+
+    for (stream, response) in [(true, sse_body(&events)), (false, body.to_string())] {
+        let (url, server) = stub_server(vec![(200, response)]);
+        let mut config = Config {
+            api_key: Some("key".to_string()),
+            stream,
+            ..Config::default()
+        };
+        config.claude_api.base_url = url;
+        let mut ctx = context("claude-api", config);
+        ctx.think_hard = true;
+
+        let response = ctx
+            .provider
+            .generate(&ctx, &prompt(), &Progress::hidden())
+            .unwrap();
+        assert_eq!(response.message, "rename parse fn", "stream: {stream}");
+        server.join().unwrap();
+    }
+
+    // a reply with neither is still an error
+    let (url, server) = stub_server(vec![(
+        200,
+        r#"{"content": [{"type": "thinking", "thinking": "hmm"}], "usage": {}}"#.to_string(),
+    )]);
+    let mut config = Config {
+        api_key: Some("key".to_string()),
+        stream: false,
+        ..Config::default()
+    };
+    config.claude_api.base_url = url;
+    let mut ctx = context("claude-api", config);
+    ctx.think_hard = true;
+    let error = ctx
+        .provider
+        .generate(&ctx, &prompt(), &Progress::hidden())
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "claude api response missing commit message"
+    );
+    server.join().unwrap();
+}
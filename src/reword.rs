@@ -0,0 +1,65 @@
+use crate::context::AppContext;
+use crate::generation::{SizedChanges, first_line, process_changes, sized_changes};
+use crate::git;
+use crate::ui::Input;
+use crate::{status, warning};
+use anyhow::Result;
+use std::path::Path;
+
+/// generate a new message for each commit in `range`, oldest first, for the
+/// user to accept, edit or decline, then rewrite the branch with them
+pub fn run(ctx: &mut AppContext, path: &Path, range: &str, input: &mut dyn Input) -> Result<()> {
+    let commits = git::commits_in_range(path, range)?;
+
+    let mut messages = Vec::new();
+    for (i, (oid, message)) in commits.iter().enumerate() {
+        status!(
+            "commit {} ({} of {}): {}",
+            git::short_id(*oid),
+            i + 1,
+            commits.len(),
+            first_line(message)
+        );
+
+        // the current message is kept if generation fails
+        ctx.reset();
+        ctx.commit_description = message.trim_end().to_string();
+        ctx.multi_line = message.trim_end().contains('\n');
+
+        let generated = ctx.config.generated.clone();
+        let sized = sized_changes(ctx, input, |context_lines| {
+            git::get_commit_changes(path, *oid, context_lines, &generated)
+        })?;
+        let reworded = match sized {
+            SizedChanges::Changes(changeset) => process_changes(ctx, &changeset, input)?,
+            SizedChanges::Empty => {
+                warning!("commit is empty, keeping its message");
+                None
+            }
+            // skipping one commit shouldn't lose the messages of the others
+            SizedChanges::Skipped => {
+                warning!("keeping the commit's message");
+                None
+            }
+        };
+
+        // declined and unchanged messages are kept
+        messages.push((
+            *oid,
+            reworded.filter(|reworded| reworded.trim_end() != message.trim_end()),
+        ));
+    }
+
+    let count = messages.iter().filter(|(_, m)| m.is_some()).count();
+    if let Some(backup) = git::reword(path, &messages)? {
+        status!(
+            "reworded {} {}; the original branch is saved as {}",
+            count,
+            if count == 1 { "commit" } else { "commits" },
+            backup
+        );
+    } else {
+        status!("no messages changed");
+    }
+    Ok(())
+}
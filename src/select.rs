@@ -0,0 +1,109 @@
+use crate::git::{self, ChangeSet, FileChange, Hunk, HunkRange, Selection, status_char};
+use crate::ui::Input;
+use anyhow::{Result, bail};
+use std::path::Path;
+
+/// ask the user which files, and which hunks of each file, to commit
+///
+/// "all" and "done" answer for the remaining files or hunks, as `git add -p`
+pub fn choose(path: &Path, changeset: &ChangeSet, input: &mut dyn Input) -> Result<Selection> {
+    let hunks = git::hunks(path, changeset)?;
+    let mut selection = Selection::default();
+    let mut remaining: Option<String> = None;
+
+    for (file, hunks) in changeset.files.iter().zip(&hunks) {
+        let answer = if let Some(answer) = &remaining {
+            answer.clone()
+        } else {
+            print_file(file, hunks.len());
+            let mut options = vec!["yes", "no"];
+            if hunks.len() > 1 {
+                options.push("hunks");
+            }
+            options.extend(["all", "done"]);
+            input.prompt(&options)?
+        };
+        match answer.as_str() {
+            "y" | "a" => {
+                if answer == "a" {
+                    remaining = Some(String::from("y"));
+                }
+                selection.files.insert(file.path.clone(), None);
+            }
+            "h" => {
+                let chosen = choose_hunks(hunks, input)?;
+                if chosen.len() == hunks.len() {
+                    selection.files.insert(file.path.clone(), None);
+                } else if !chosen.is_empty() {
+                    selection.files.insert(file.path.clone(), Some(chosen));
+                }
+            }
+            "d" => remaining = Some(String::from("n")),
+            _ => {}
+        }
+    }
+
+    if selection.files.is_empty() {
+        bail!("nothing selected");
+    }
+    Ok(selection)
+}
+
+/// ask which of a file's hunks to commit
+fn choose_hunks(hunks: &[Hunk], input: &mut dyn Input) -> Result<Vec<HunkRange>> {
+    let mut chosen = Vec::new();
+    let mut remaining: Option<String> = None;
+
+    for (i, hunk) in hunks.iter().enumerate() {
+        let answer = if let Some(answer) = &remaining {
+            answer.clone()
+        } else {
+            print_hunk(hunk);
+            crate::status!("hunk {} of {}", i + 1, hunks.len());
+            input.prompt(&["yes", "no", "all", "done"])?
+        };
+        match answer.as_str() {
+            "y" | "a" => {
+                if answer == "a" {
+                    remaining = Some(String::from("y"));
+                }
+                chosen.push(hunk.range);
+            }
+            "d" => remaining = Some(String::from("n")),
+            _ => {}
+        }
+    }
+
+    Ok(chosen)
+}
+
+fn print_file(file: &FileChange, hunk_count: usize) {
+    use colored::Colorize;
+
+    let path = match &file.old_path {
+        Some(old_path) => format!("{old_path} → {}", file.path),
+        None => file.path.clone(),
+    };
+    let suffix = if hunk_count > 1 {
+        format!(" ({hunk_count} hunks)")
+    } else {
+        String::new()
+    };
+    crate::info!("{} {}{}", status_char(file.status), path.bold(), suffix);
+}
+
+fn print_hunk(hunk: &Hunk) {
+    use colored::Colorize;
+
+    for line in hunk.text.lines() {
+        if line.starts_with("@@") {
+            crate::info!(line.cyan());
+        } else if line.starts_with('+') {
+            crate::info!(line.green());
+        } else if line.starts_with('-') {
+            crate::info!(line.red());
+        } else {
+            crate::info!(line);
+        }
+    }
+}
@@ -0,0 +1,394 @@
+use crate::claude::{self, ClaudeResponse};
+use crate::context::{AppContext, ModelTier};
+use crate::generation::{record_usage, resolve_generation, start_progress};
+use crate::git::{self, ChangeSet, HunkRange, Scope, Selection, status_char};
+use crate::ui::Input;
+use crate::{error, info, ledger, status, warning};
+use anyhow::{Result, bail};
+use serde::Deserialize;
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// a file, or a hunk of a file, which can be assigned to a commit
+struct Change {
+    /// names the change for the model and the user, eg. "src/main.rs#2"
+    id: String,
+    path: String,
+    /// None for the whole file
+    hunk: Option<HunkRange>,
+    /// shown to the model alongside the id
+    description: String,
+}
+
+/// a commit proposed by the model, as returned
+#[derive(Deserialize)]
+struct ProposedCommit {
+    message: String,
+    changes: Vec<String>,
+}
+
+/// a commit in the plan, with the indexes of its changes
+struct Commit {
+    message: String,
+    changes: Vec<usize>,
+}
+
+/// the commits the changes are split into
+struct Plan {
+    commits: Vec<Commit>,
+}
+
+impl Plan {
+    /// parse the model's response; unknown and repeated changes are dropped
+    fn from_response(changes: &[Change], response: &str) -> Result<Self> {
+        // the json may be tagged as such after the opening backticks
+        let json = response.strip_prefix("json").unwrap_or(response);
+        let proposed: Vec<ProposedCommit> = serde_json::from_str(json)
+            .map_err(|e| anyhow::anyhow!("invalid split from provider: {e}"))?;
+
+        let mut assigned = vec![false; changes.len()];
+        let mut commits = Vec::new();
+        for commit in proposed {
+            let mut indexes = Vec::new();
+            for id in &commit.changes {
+                match changes.iter().position(|change| &change.id == id) {
+                    Some(index) if !assigned[index] => {
+                        assigned[index] = true;
+                        indexes.push(index);
+                    }
+                    Some(_) => warning!("{} is in more than one commit", id),
+                    None => warning!("ignoring unknown change {}", id),
+                }
+            }
+            if !indexes.is_empty() {
+                commits.push(Commit {
+                    message: commit.message.trim().to_string(),
+                    changes: indexes,
+                });
+            }
+        }
+        if commits.is_empty() {
+            bail!("provider didn't split the changes into any commits");
+        }
+        Ok(Self { commits })
+    }
+
+    /// parse the plan as edited by the user; see `to_text`
+    fn from_text(changes: &[Change], text: &str) -> Result<Self> {
+        let mut commits: Vec<Commit> = Vec::new();
+        let mut assigned = vec![false; changes.len()];
+        for line in text.lines() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            if !line.starts_with(char::is_whitespace) {
+                commits.push(Commit {
+                    message: trimmed.to_string(),
+                    changes: Vec::new(),
+                });
+                continue;
+            }
+            let Some(commit) = commits.last_mut() else {
+                bail!("{trimmed} isn't below a commit message");
+            };
+            let Some(index) = changes.iter().position(|change| change.id == trimmed) else {
+                bail!("unknown change {trimmed}");
+            };
+            if assigned[index] {
+                bail!("{trimmed} is in more than one commit");
+            }
+            assigned[index] = true;
+            commit.changes.push(index);
+        }
+
+        if let Some(commit) = commits.iter().find(|commit| commit.changes.is_empty()) {
+            bail!("commit \"{}\" has no changes", commit.message);
+        }
+        if commits.is_empty() {
+            bail!("no commits");
+        }
+        Ok(Self { commits })
+    }
+
+    /// the plan as text for the user to edit: each commit message is followed
+    /// by its indented changes, and changes which won't be committed are
+    /// listed in comments
+    fn to_text(&self, changes: &[Change]) -> String {
+        let mut text = String::new();
+        for commit in &self.commits {
+            let _ = writeln!(text, "{}", commit.message);
+            for &index in &commit.changes {
+                let _ = writeln!(text, "    {}", changes[index].id);
+            }
+            text.push('\n');
+        }
+        let unassigned = self.unassigned(changes);
+        if !unassigned.is_empty() {
+            text.push_str("# not committed:\n");
+            for index in unassigned {
+                let _ = writeln!(text, "#   {}", changes[index].id);
+            }
+        }
+        text.trim_end().to_string()
+    }
+
+    /// indexes of changes which aren't in any commit
+    fn unassigned(&self, changes: &[Change]) -> Vec<usize> {
+        (0..changes.len())
+            .filter(|index| {
+                !self
+                    .commits
+                    .iter()
+                    .any(|commit| commit.changes.contains(index))
+            })
+            .collect()
+    }
+
+    /// the message and selection of each commit, in order
+    fn selections(&self, changes: &[Change]) -> Vec<(String, Selection)> {
+        self.commits
+            .iter()
+            .map(|commit| {
+                let mut selection = Selection::default();
+                for &index in &commit.changes {
+                    let change = &changes[index];
+                    let hunks = selection.files.entry(change.path.clone()).or_default();
+                    if let Some(hunk) = change.hunk {
+                        hunks.get_or_insert_with(Vec::new).push(hunk);
+                    }
+                }
+                (commit.message.clone(), selection)
+            })
+            .collect()
+    }
+}
+
+/// the changes which can be assigned to commits: hunks of files with more
+/// than one, and other files whole
+fn changes(changeset: &ChangeSet) -> Result<Vec<Change>> {
+    let hunks = git::hunks(&changeset.repo, changeset)?;
+    let mut changes = Vec::new();
+    for (file, hunks) in changeset.files.iter().zip(hunks) {
+        let status = status_char(file.status);
+        if hunks.len() > 1 {
+            for (i, hunk) in hunks.iter().enumerate() {
+                changes.push(Change {
+                    id: format!("{}#{}", file.path, i + 1),
+                    path: file.path.clone(),
+                    hunk: Some(hunk.range),
+                    description: format!(
+                        "{status} hunk {} of {}: {}",
+                        i + 1,
+                        hunks.len(),
+                        hunk.text.lines().next().unwrap_or("")
+                    ),
+                });
+            }
+        } else {
+            changes.push(Change {
+                id: file.path.clone(),
+                path: file.path.clone(),
+                hunk: None,
+                description: match &file.old_path {
+                    Some(old_path) => format!("{status} renamed from {old_path}"),
+                    None => format!("{status} whole file"),
+                },
+            });
+        }
+    }
+    Ok(changes)
+}
+
+/// ask the model to split the changes into commits and refine the plan with
+/// the user, returning each commit's message and selection, or None if the
+/// user aborted
+pub fn plan(
+    ctx: &mut AppContext,
+    changeset: &ChangeSet,
+    input: &mut dyn Input,
+) -> Result<Option<Vec<(String, Selection)>>> {
+    let changes = changes(changeset)?;
+    let mut list = String::new();
+    for change in &changes {
+        let _ = writeln!(list, "{}: {}", change.id, change.description);
+    }
+
+    let mut plan: Option<Plan> = None;
+    let mut edited = false;
+    loop {
+        if ctx.regenerate {
+            let prompt = claude::get_split_prompt(ctx, changeset, list.trim_end());
+            if let Some(response) = generate(ctx, changeset, &prompt, input)? {
+                match Plan::from_response(&changes, &response.message) {
+                    Ok(generated) => {
+                        resolve_generation(ctx, ledger::Outcome::Rerolled);
+                        ctx.generation_id = Some(record_usage(ctx, changeset, &[response]));
+                        plan = Some(generated);
+                        edited = false;
+                    }
+                    Err(e) => {
+                        record_usage(ctx, changeset, &[response]);
+                        error!("{}", e);
+                    }
+                }
+            }
+        }
+        ctx.regenerate = false;
+        ctx.model = ModelTier::Fast;
+        ctx.think_hard = false;
+
+        let Some(current) = &plan else {
+            // nothing to show; the user can retry or give up
+            if input.prompt(&["reroll", "no"])? == "n" {
+                return Ok(None);
+            }
+            ctx.regenerate = true;
+            ctx.model = ModelTier::Smart;
+            ctx.think_hard = true;
+            continue;
+        };
+        display(current, &changes);
+
+        match input
+            .prompt(&["YES", "no", "edit", "reroll", "prompt"])?
+            .as_str()
+        {
+            "y" => {
+                let outcome = if edited {
+                    ledger::Outcome::Edited
+                } else {
+                    ledger::Outcome::Accepted
+                };
+                resolve_generation(ctx, outcome);
+                return Ok(Some(current.selections(&changes)));
+            }
+            "n" => {
+                resolve_generation(ctx, ledger::Outcome::Rejected);
+                return Ok(None);
+            }
+            "e" => {
+                let text = input.edit_multi_line(&current.to_text(&changes))?;
+                match Plan::from_text(&changes, &text) {
+                    Ok(updated) => {
+                        plan = Some(updated);
+                        edited = true;
+                    }
+                    Err(e) => error!("{}", e),
+                }
+            }
+            "r" => {
+                ctx.regenerate = true;
+                ctx.model = ModelTier::Smart;
+                ctx.think_hard = true;
+            }
+            "p" => {
+                status!("provide extra claude prompt context:");
+                let old_prompt_extra = ctx.prompt_extra.clone();
+                ctx.prompt_extra = input.edit_one_line(&ctx.prompt_extra)?;
+                ctx.regenerate = ctx.prompt_extra != old_prompt_extra;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// generate the plan with a spinner, letting the user cancel; None if
+/// nothing was generated
+fn generate(
+    ctx: &AppContext,
+    changeset: &ChangeSet,
+    prompt: &claude::Prompt,
+    input: &mut dyn Input,
+) -> Result<Option<ClaudeResponse>> {
+    let file_count = changeset.files.len();
+    let summary = format!(
+        "{} [{} {}]",
+        changeset.source(),
+        file_count,
+        if file_count == 1 { "file" } else { "files" }
+    );
+    let estimate = claude::estimate_prompt(ctx, prompt, 1);
+    let Some(progress) = start_progress(ctx, &estimate, 0.0, input, &summary)? else {
+        return Ok(None);
+    };
+    progress.status("splitting into commits");
+
+    let (waited, result) = std::thread::scope(|scope| {
+        let handle = scope.spawn(|| claude::send(ctx, prompt, &progress));
+        let waited = input.wait_for_cancel(&|| handle.is_finished());
+        if !matches!(waited, Ok(false)) {
+            progress.cancel();
+        }
+        let result = handle
+            .join()
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("generation thread panicked")));
+        (waited, result)
+    });
+    progress.finish();
+
+    if waited? {
+        warning!("generation cancelled");
+        return Ok(None);
+    }
+    match result {
+        Ok(response) => Ok(Some(response)),
+        Err(e) => {
+            error!("{}", e);
+            Ok(None)
+        }
+    }
+}
+
+/// show the plan: each commit's message and changes
+fn display(plan: &Plan, changes: &[Change]) {
+    use colored::Colorize;
+
+    info!();
+    for (i, commit) in plan.commits.iter().enumerate() {
+        info!("{} {}", format!("{})", i + 1).cyan(), commit.message);
+        for &index in &commit.changes {
+            info!("   {}", changes[index].id);
+        }
+    }
+    let unassigned = plan.unassigned(changes);
+    if !unassigned.is_empty() {
+        warning!("not committed:");
+        for index in unassigned {
+            info!("   {}", changes[index].id);
+        }
+    }
+    info!();
+}
+
+/// stage and commit each commit of the plan for `changeset` in turn
+pub fn commit(
+    path: &Path,
+    changeset: &ChangeSet,
+    generated: &[String],
+    commits: &[(String, Selection)],
+) -> Result<()> {
+    for (message, selection) in commits {
+        let Some(changeset) = git::get_changes(
+            path,
+            changeset.context_lines,
+            Scope::Auto,
+            &changeset.pathspecs,
+            generated,
+            Some(selection),
+        )?
+        else {
+            bail!("no changes left to commit");
+        };
+        if changeset.files.is_empty() {
+            warning!("nothing left to commit for \"{}\"", message);
+            continue;
+        }
+        git::stage(path, &changeset)?;
+        git::commit(path, message, &changeset)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,78 @@
+use super::*;
+
+/// helper to create the changes of a file with two hunks and a new file
+fn changes() -> Vec<Change> {
+    let change = |id: &str, path: &str, hunk: Option<HunkRange>| Change {
+        id: id.to_string(),
+        path: path.to_string(),
+        hunk,
+        description: String::new(),
+    };
+    vec![
+        change("a.txt#1", "a.txt", Some(HunkRange::at(1, 5))),
+        change("a.txt#2", "a.txt", Some(HunkRange::at(16, 5))),
+        change("b.txt", "b.txt", None),
+    ]
+}
+
+#[test]
+fn test_from_response() {
+    let changes = changes();
+    let response = r#"json
+[
+  {"message": "change a", "changes": ["a.txt#1", "unknown"]},
+  {"message": "change a and add b", "changes": ["a.txt#2", "b.txt", "a.txt#1"]},
+  {"message": "nothing", "changes": []}
+]"#;
+    let plan = Plan::from_response(&changes, response).unwrap();
+
+    // unknown, repeated and empty entries are dropped
+    assert_eq!(plan.commits.len(), 2);
+    assert_eq!(plan.commits[0].changes, [0]);
+    assert_eq!(plan.commits[1].changes, [1, 2]);
+
+    assert!(Plan::from_response(&changes, "[]").is_err());
+    assert!(Plan::from_response(&changes, "change a").is_err());
+}
+
+#[test]
+fn test_text() {
+    let changes = changes();
+    let plan = Plan::from_text(&changes, "change a\n    a.txt#2\n    a.txt#1\n").unwrap();
+    assert_eq!(
+        plan.to_text(&changes),
+        "change a\n    a.txt#2\n    a.txt#1\n\n# not committed:\n#   b.txt"
+    );
+
+    // the text round trips, and changes can be moved between commits
+    let text = plan
+        .to_text(&changes)
+        .replace("#   b.txt", "add b\n    b.txt");
+    let plan = Plan::from_text(&changes, &text).unwrap();
+    assert_eq!(plan.commits.len(), 2);
+    assert!(plan.unassigned(&changes).is_empty());
+
+    let error = |text| Plan::from_text(&changes, text).err().unwrap().to_string();
+    assert_eq!(error("    b.txt"), "b.txt isn't below a commit message");
+    assert_eq!(error("add c\n    c.txt"), "unknown change c.txt");
+    assert_eq!(
+        error("add b\n    b.txt\n    b.txt"),
+        "b.txt is in more than one commit"
+    );
+    assert_eq!(error("add b\n"), "commit \"add b\" has no changes");
+    assert_eq!(error("# nothing"), "no commits");
+}
+
+#[test]
+fn test_selections() {
+    let changes = changes();
+    let plan = Plan::from_text(&changes, "one\n    a.txt#1\n    b.txt\ntwo\n    a.txt#2").unwrap();
+    let selections = plan.selections(&changes);
+    assert_eq!(selections[0].0, "one");
+    assert_eq!(
+        selections[0].1.files["a.txt"],
+        Some(vec![HunkRange::at(1, 5)])
+    );
+    assert_eq!(selections[0].1.files["b.txt"], None);
+    assert_eq!(selections[1].1.files.len(), 1);
+}
@@ -0,0 +1,308 @@
+use crate::cli::{StatsFormat, StatsGroup};
+use crate::ledger::{Entry, Outcome, SECS_PER_DAY};
+use anyhow::Result;
+use num_format::{Locale, ToFormattedString};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write as _;
+
+const GROUPS: [StatsGroup; 3] = [StatsGroup::Repo, StatsGroup::Model, StatsGroup::Week];
+
+/// totals for a group of ledger entries
+#[derive(Default, Serialize)]
+struct Summary {
+    generations: usize,
+    accepted: usize,
+    edited: usize,
+    rerolled: usize,
+    rejected: usize,
+    /// share of generations with a known outcome that were committed
+    acceptance_rate: Option<f64>,
+    input_tokens: u64,
+    output_tokens: u64,
+    /// total cost in USD of entries with a known cost
+    cost: Option<f64>,
+}
+
+impl Summary {
+    #[allow(clippy::cast_precision_loss)]
+    fn new(entries: &[&Entry]) -> Self {
+        let mut summary = Summary::default();
+        let mut seen = HashSet::new();
+        for entry in entries {
+            summary.input_tokens += entry.input_tokens;
+            summary.output_tokens += entry.output_tokens;
+            if let Some(cost) = entry.cost {
+                *summary.cost.get_or_insert(0.0) += cost;
+            }
+
+            // candidates generated together share an id and an outcome
+            if !seen.insert(entry.id.as_str()) {
+                continue;
+            }
+            summary.generations += 1;
+            match entry.outcome {
+                Some(Outcome::Accepted) => summary.accepted += 1,
+                Some(Outcome::Edited) => summary.edited += 1,
+                Some(Outcome::Rerolled) => summary.rerolled += 1,
+                Some(Outcome::Rejected) => summary.rejected += 1,
+                None => {}
+            }
+        }
+
+        let committed = summary.accepted + summary.edited;
+        let resolved = committed + summary.rerolled + summary.rejected;
+        if resolved > 0 {
+            summary.acceptance_rate = Some(committed as f64 / resolved as f64);
+        }
+        summary
+    }
+}
+
+/// render ledger entries in the requested format
+///
+/// without a grouping, the table shows every grouping while csv and json
+/// export the entries themselves
+pub fn render(entries: &[Entry], by: Option<StatsGroup>, format: StatsFormat) -> Result<String> {
+    match (format, by) {
+        (StatsFormat::Table, _) if entries.is_empty() => {
+            Ok(String::from("no generations recorded\n"))
+        }
+        (StatsFormat::Table, Some(by)) => Ok(table(entries, by)),
+        (StatsFormat::Table, None) => {
+            let all: Vec<&Entry> = entries.iter().collect();
+            let total = Summary::new(&all);
+            let mut output = format!(
+                "{} generations, {} tokens, {} USD, {} accepted\n",
+                total.generations.to_formatted_string(&Locale::en),
+                (total.input_tokens + total.output_tokens).to_formatted_string(&Locale::en),
+                format_cost(total.cost),
+                format_rate(total.acceptance_rate),
+            );
+            for by in GROUPS {
+                output.push('\n');
+                output.push_str(&table(entries, by));
+            }
+            Ok(output)
+        }
+        (StatsFormat::Csv, Some(by)) => {
+            let mut output = csv_row(&[
+                group_name(by),
+                "generations",
+                "accepted",
+                "edited",
+                "rerolled",
+                "rejected",
+                "acceptance_rate",
+                "input_tokens",
+                "output_tokens",
+                "cost",
+            ]);
+            for (key, entries) in group(entries, by) {
+                let summary = Summary::new(&entries);
+                output.push_str(&csv_row(&[
+                    &key,
+                    &summary.generations.to_string(),
+                    &summary.accepted.to_string(),
+                    &summary.edited.to_string(),
+                    &summary.rerolled.to_string(),
+                    &summary.rejected.to_string(),
+                    &summary
+                        .acceptance_rate
+                        .map_or_else(String::new, |rate| format!("{rate:.4}")),
+                    &summary.input_tokens.to_string(),
+                    &summary.output_tokens.to_string(),
+                    &summary
+                        .cost
+                        .map_or_else(String::new, |cost| format!("{cost:.6}")),
+                ]));
+            }
+            Ok(output)
+        }
+        (StatsFormat::Csv, None) => {
+            let mut output = csv_row(&[
+                "id",
+                "time",
+                "repo",
+                "backend",
+                "model",
+                "input_tokens",
+                "output_tokens",
+                "cache_read_tokens",
+                "cache_write_tokens",
+                "cost",
+                "outcome",
+            ]);
+            for entry in entries {
+                output.push_str(&csv_row(&[
+                    &entry.id,
+                    &format_time(entry.timestamp),
+                    &entry.repo,
+                    &entry.backend,
+                    &entry.model,
+                    &entry.input_tokens.to_string(),
+                    &entry.output_tokens.to_string(),
+                    &entry.cache_read_tokens.to_string(),
+                    &entry.cache_write_tokens.to_string(),
+                    &entry
+                        .cost
+                        .map_or_else(String::new, |cost| format!("{cost:.6}")),
+                    entry.outcome.map_or("", Outcome::as_str),
+                ]));
+            }
+            Ok(output)
+        }
+        (StatsFormat::Json, Some(by)) => {
+            let mut rows = Vec::new();
+            for (key, entries) in group(entries, by) {
+                let mut row = serde_json::to_value(Summary::new(&entries))
+                    .map_err(|e| anyhow::anyhow!("failed to serialise stats: {e}"))?;
+                row[group_name(by)] = key.into();
+                rows.push(row);
+            }
+            to_json(&rows)
+        }
+        (StatsFormat::Json, None) => to_json(entries),
+    }
+}
+
+/// a table of totals for each group
+fn table(entries: &[Entry], by: StatsGroup) -> String {
+    let mut rows = vec![
+        [
+            group_name(by),
+            "generations",
+            "accepted",
+            "edited",
+            "rerolled",
+            "rejected",
+            "rate",
+            "tokens",
+            "cost",
+        ]
+        .map(String::from),
+    ];
+    for (key, entries) in group(entries, by) {
+        let summary = Summary::new(&entries);
+        rows.push([
+            key,
+            summary.generations.to_formatted_string(&Locale::en),
+            summary.accepted.to_formatted_string(&Locale::en),
+            summary.edited.to_formatted_string(&Locale::en),
+            summary.rerolled.to_formatted_string(&Locale::en),
+            summary.rejected.to_formatted_string(&Locale::en),
+            format_rate(summary.acceptance_rate),
+            (summary.input_tokens + summary.output_tokens).to_formatted_string(&Locale::en),
+            format_cost(summary.cost),
+        ]);
+    }
+
+    // the first column is left aligned, numbers are right aligned
+    let widths: Vec<usize> = (0..rows[0].len())
+        .map(|i| {
+            rows.iter()
+                .map(|row| row[i].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let mut output = String::new();
+    for row in &rows {
+        let mut line = format!("{:<width$}", row[0], width = widths[0]);
+        for (cell, width) in row.iter().zip(&widths).skip(1) {
+            let _ = write!(line, "  {cell:>width$}");
+        }
+        output.push_str(line.trim_end());
+        output.push('\n');
+    }
+    output
+}
+
+/// entries grouped by key, in key order
+fn group(entries: &[Entry], by: StatsGroup) -> BTreeMap<String, Vec<&Entry>> {
+    let mut groups: BTreeMap<String, Vec<&Entry>> = BTreeMap::new();
+    for entry in entries {
+        let key = match by {
+            StatsGroup::Repo => entry.repo.clone(),
+            StatsGroup::Model => entry.model.clone(),
+            StatsGroup::Week => format_date(week_start(entry.timestamp)),
+        };
+        groups.entry(key).or_default().push(entry);
+    }
+    groups
+}
+
+fn group_name(by: StatsGroup) -> &'static str {
+    match by {
+        StatsGroup::Repo => "repo",
+        StatsGroup::Model => "model",
+        StatsGroup::Week => "week",
+    }
+}
+
+fn format_rate(rate: Option<f64>) -> String {
+    rate.map_or_else(|| String::from("-"), |rate| format!("{:.0}%", rate * 100.0))
+}
+
+fn format_cost(cost: Option<f64>) -> String {
+    cost.map_or_else(|| String::from("-"), |cost| format!("${cost:.4}"))
+}
+
+fn to_json<T: Serialize + ?Sized>(value: &T) -> Result<String> {
+    serde_json::to_string_pretty(value)
+        .map(|json| json + "\n")
+        .map_err(|e| anyhow::anyhow!("failed to serialise stats: {e}"))
+}
+
+/// a line of csv, quoting fields where required
+fn csv_row(fields: &[&str]) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                (*field).to_string()
+            }
+        })
+        .collect();
+    fields.join(",") + "\n"
+}
+
+/// days since the unix epoch of the monday starting the timestamp's week
+fn week_start(timestamp: u64) -> u64 {
+    let days = timestamp / SECS_PER_DAY;
+    // the epoch was a thursday, so its first week is cut short rather than
+    // starting before it, eg. for zero timestamps
+    days.saturating_sub((days + 3) % 7)
+}
+
+/// iso 8601 date, eg. "2025-01-31", of days since the unix epoch
+fn format_date(days: u64) -> String {
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// iso 8601 utc time, eg. "2025-01-31T12:00:00Z", of a unix timestamp
+fn format_time(timestamp: u64) -> String {
+    let secs = timestamp % SECS_PER_DAY;
+    format!(
+        "{}T{:02}:{:02}:{:02}Z",
+        format_date(timestamp / SECS_PER_DAY),
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,124 @@
+use super::*;
+
+/// helper to create an entry
+fn entry(id: &str, repo: &str, model: &str, cost: Option<f64>, outcome: Option<Outcome>) -> Entry {
+    Entry {
+        id: id.to_string(),
+        // friday 2026-10-16 13:05:09 utc
+        timestamp: 1_792_155_909,
+        repo: repo.to_string(),
+        backend: "API".to_string(),
+        model: model.to_string(),
+        input_tokens: 1000,
+        output_tokens: 20,
+        cache_read_tokens: 0,
+        cache_write_tokens: 0,
+        cost,
+        outcome,
+    }
+}
+
+fn entries() -> Vec<Entry> {
+    vec![
+        // two candidates generated together
+        entry("a", "/one", "haiku", Some(0.25), Some(Outcome::Rerolled)),
+        entry("a", "/one", "haiku", Some(0.25), Some(Outcome::Rerolled)),
+        entry("b", "/one", "sonnet", Some(1.0), Some(Outcome::Edited)),
+        entry("c", "/two, three", "haiku", None, Some(Outcome::Accepted)),
+        entry("d", "/two, three", "haiku", None, None),
+    ]
+}
+
+#[test]
+fn test_summary() {
+    let entries = entries();
+    let all: Vec<&Entry> = entries.iter().collect();
+    let summary = Summary::new(&all);
+
+    // candidates count once, unknown outcomes don't affect the rate
+    assert_eq!(summary.generations, 4);
+    assert_eq!(summary.accepted, 1);
+    assert_eq!(summary.edited, 1);
+    assert_eq!(summary.rerolled, 1);
+    assert_eq!(summary.rejected, 0);
+    assert!((summary.acceptance_rate.unwrap() - 2.0 / 3.0).abs() < 1e-9);
+    assert_eq!(summary.input_tokens, 5000);
+    assert!((summary.cost.unwrap() - 1.5).abs() < 1e-9);
+
+    let unpriced = entry("c", "/two", "haiku", None, None);
+    let summary = Summary::new(&[&unpriced]);
+    assert_eq!(summary.acceptance_rate, None);
+    assert_eq!(summary.cost, None);
+}
+
+#[test]
+fn test_table() {
+    let output = render(&entries(), Some(StatsGroup::Model), StatsFormat::Table).unwrap();
+    assert_eq!(
+        output,
+        "\
+model   generations  accepted  edited  rerolled  rejected  rate  tokens     cost
+haiku             3         1       0         1         0   50%   4,080  $0.5000
+sonnet            1         0       1         0         0  100%   1,020  $1.0000
+"
+    );
+
+    let output = render(&[], None, StatsFormat::Table).unwrap();
+    assert_eq!(output, "no generations recorded\n");
+}
+
+#[test]
+fn test_csv() {
+    let output = render(&entries(), Some(StatsGroup::Repo), StatsFormat::Csv).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "repo,generations,accepted,edited,rerolled,rejected,acceptance_rate,input_tokens,output_tokens,cost",
+            "/one,2,0,1,1,0,0.5000,3000,60,1.500000",
+            "\"/two, three\",2,1,0,0,0,1.0000,2000,40,",
+        ]
+    );
+
+    let output = render(&entries(), None, StatsFormat::Csv).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 6);
+    assert_eq!(
+        lines[5],
+        "d,2026-10-16T13:05:09Z,\"/two, three\",API,haiku,1000,20,0,0,,"
+    );
+}
+
+#[test]
+fn test_json() {
+    let output = render(&entries(), Some(StatsGroup::Week), StatsFormat::Json).unwrap();
+    let rows: serde_json::Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(rows[0]["week"], "2026-10-12");
+    assert_eq!(rows[0]["generations"], 4);
+
+    let output = render(&entries(), None, StatsFormat::Json).unwrap();
+    let rows: serde_json::Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(rows[2]["outcome"], "edited");
+    assert!(rows[4].get("outcome").is_none());
+}
+
+#[test]
+fn test_dates() {
+    assert_eq!(format_date(0), "1970-01-01");
+    assert_eq!(format_date(19_782), "2024-02-29");
+    assert_eq!(format_date(20_742), "2026-10-16");
+
+    // weeks start on monday
+    assert_eq!(week_start(20_742 * SECS_PER_DAY), 20_738);
+    assert_eq!(week_start(20_738 * SECS_PER_DAY), 20_738);
+    assert_eq!(week_start(20_737 * SECS_PER_DAY + 1), 20_731);
+
+    // the first days of the epoch don't underflow
+    assert_eq!(week_start(0), 0);
+    assert_eq!(week_start(2 * SECS_PER_DAY), 0);
+    assert_eq!(week_start(4 * SECS_PER_DAY), 4);
+    let mut zero = entry("e", "/one", "haiku", None, None);
+    zero.timestamp = 0;
+    let output = render(&[zero], Some(StatsGroup::Week), StatsFormat::Csv).unwrap();
+    assert!(output.contains("1970-01-01"), "{output}");
+}
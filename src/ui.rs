@@ -1,4 +1,9 @@
+use crate::constants::CANCEL_POLL_MS;
 use anyhow::{Context, Result};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 #[macro_export]
 macro_rules! warning {
@@ -66,6 +71,42 @@ macro_rules! info {
     }};
 }
 
+/// source of user responses, so the interactive workflow can be scripted
+pub trait Input {
+    /// see `prompt`
+    fn prompt(&mut self, options: &[&str]) -> Result<String>;
+
+    /// see `edit_one_line`
+    fn edit_one_line(&mut self, line: &str) -> Result<String>;
+
+    /// see `edit_multi_line`
+    fn edit_multi_line(&mut self, text: &str) -> Result<String>;
+
+    /// see `wait_for_cancel`
+    fn wait_for_cancel(&mut self, done: &dyn Fn() -> bool) -> Result<bool>;
+}
+
+/// input from the user's terminal
+pub struct Terminal;
+
+impl Input for Terminal {
+    fn prompt(&mut self, options: &[&str]) -> Result<String> {
+        prompt(options)
+    }
+
+    fn edit_one_line(&mut self, line: &str) -> Result<String> {
+        edit_one_line(line)
+    }
+
+    fn edit_multi_line(&mut self, text: &str) -> Result<String> {
+        edit_multi_line(text)
+    }
+
+    fn wait_for_cancel(&mut self, done: &dyn Fn() -> bool) -> Result<bool> {
+        wait_for_cancel(done)
+    }
+}
+
 pub fn prompt(options: &[&str]) -> Result<String> {
     use crossterm::{
         event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
@@ -230,3 +271,192 @@ pub fn edit_multi_line(text: &str) -> Result<String> {
     // temp_file will be automatically cleaned up when it goes out of scope
     Ok(edited)
 }
+
+/// wait until `done` returns true, or the user presses esc or ctrl-c
+///
+/// returns true if the user cancelled.  other keys are discarded, so typing
+/// ahead doesn't answer the next prompt
+pub fn wait_for_cancel(done: &dyn Fn() -> bool) -> Result<bool> {
+    use crossterm::{
+        event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
+        terminal::{disable_raw_mode, enable_raw_mode},
+    };
+
+    // raw mode stops ctrl-c from killing the process
+    enable_raw_mode().context("this command requires an interactive terminal")?;
+
+    let result = loop {
+        if done() {
+            break Ok(false);
+        }
+        match event::poll(Duration::from_millis(CANCEL_POLL_MS)) {
+            Ok(false) => {}
+            Ok(true) => match event::read() {
+                Ok(Event::Key(KeyEvent {
+                    code, modifiers, ..
+                })) => {
+                    if code == KeyCode::Esc
+                        || (code == KeyCode::Char('c') && modifiers.contains(KeyModifiers::CONTROL))
+                    {
+                        break Ok(true);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => break Err(anyhow::anyhow!("failed to read key: {e}")),
+            },
+            Err(e) => break Err(anyhow::anyhow!("failed to read key: {e}")),
+        }
+    };
+
+    disable_raw_mode().ok();
+    result
+}
+
+/// error returned by providers when generation is cancelled by the user
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "generation cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// spinner shown while a commit message is being generated, with a live
+/// preview of the message as it streams in
+///
+/// also carries the user's request to cancel generation to the providers,
+/// and their warnings back, as generation runs on other threads which
+/// shouldn't write to the terminal; clones share the spinner, cancellation and
+/// warnings
+#[derive(Clone)]
+pub struct Progress {
+    spinner: Option<ProgressBar>,
+    message: String,
+    cancelled: Arc<AtomicBool>,
+    warnings: Arc<Mutex<Vec<String>>>,
+}
+
+impl Progress {
+    /// show a spinner with the given message
+    pub fn start(message: String) -> Self {
+        let spinner = ProgressBar::new_spinner();
+        spinner.set_style(
+            ProgressStyle::default_spinner()
+                .template("{spinner:.cyan} {msg:.cyan}")
+                .unwrap(),
+        );
+        spinner.set_message(message.clone());
+        spinner.enable_steady_tick(std::time::Duration::from_millis(100));
+        Self {
+            spinner: Some(spinner),
+            message,
+            cancelled: Arc::default(),
+            warnings: Arc::default(),
+        }
+    }
+
+    /// report nothing
+    pub fn hidden() -> Self {
+        Self {
+            spinner: None,
+            message: String::new(),
+            cancelled: Arc::default(),
+            warnings: Arc::default(),
+        }
+    }
+
+    /// report nothing, but be cancelled along with this progress
+    pub fn muted(&self) -> Self {
+        Self {
+            spinner: None,
+            message: String::new(),
+            cancelled: Arc::clone(&self.cancelled),
+            warnings: Arc::clone(&self.warnings),
+        }
+    }
+
+    /// ask the providers using this progress to stop
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// true if generation has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// fail with `Cancelled` if generation has been cancelled
+    pub fn check_cancelled(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(Cancelled.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// keep a warning for whoever started generation to show
+    pub fn warn(&self, warning: String) {
+        if let Ok(mut warnings) = self.warnings.lock() {
+            warnings.push(warning);
+        }
+    }
+
+    /// the warnings kept so far, which are then forgotten
+    pub fn take_warnings(&self) -> Vec<String> {
+        self.warnings
+            .lock()
+            .map(|mut warnings| std::mem::take(&mut *warnings))
+            .unwrap_or_default()
+    }
+
+    /// show a status, such as a pending retry, after the spinner message
+    pub fn status(&self, status: &str) {
+        use colored::Colorize;
+
+        if let Some(spinner) = &self.spinner {
+            spinner.set_message(format!("{} {}", self.message, status.dimmed()));
+        }
+    }
+
+    /// show the output generated so far below the spinner message
+    pub fn preview(&self, output: &str) {
+        use colored::Colorize;
+        use std::fmt::Write;
+
+        let Some(spinner) = &self.spinner else {
+            return;
+        };
+
+        // only show the message itself, which is wrapped in triple backticks
+        let text = match output.split_once("```") {
+            Some((_, after)) => {
+                let after = after.split_once('\n').map_or("", |(_, rest)| rest);
+                after
+                    .split_once("```")
+                    .map_or(after, |(message, _)| message)
+            }
+            None => output,
+        };
+
+        // the terminal is in raw mode while generating, so each line has to
+        // return the cursor to the start of the line itself
+        let mut message = self.message.clone();
+        for line in text.trim().lines() {
+            let _ = write!(message, "\n\r  {}", line.dimmed());
+        }
+        spinner.set_message(message);
+    }
+
+    /// remove the spinner, then show the warnings kept while it was shown
+    pub fn finish(&self) {
+        if let Some(spinner) = &self.spinner {
+            spinner.finish_and_clear();
+        }
+        for warning in self.take_warnings() {
+            crate::warning!(warning);
+        }
+    }
+}